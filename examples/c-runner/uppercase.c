@@ -0,0 +1,45 @@
+// Build with `cc -shared -fPIC -o libuppercase.so uppercase.c`
+// and run with `spectra test ./examples/specification.uppercase.md c:examples/c-runner/libuppercase.so::get_result`
+#include <ctype.h>
+#include <stdio.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+int32_t get_result(const uint8_t *input, size_t input_length, uint8_t **output, size_t *output_length) {
+	uint8_t *buffer = malloc(input_length);
+	if (buffer == NULL) {
+		return 1;
+	}
+	for (size_t i = 0; i < input_length; i++) {
+		buffer[i] = (uint8_t)toupper(input[i]);
+	}
+	*output = buffer;
+	*output_length = input_length;
+	return 0;
+}
+
+void get_result_free(uint8_t *output, size_t output_length) {
+	(void)output_length;
+	free(output);
+}
+
+// The number of calls so far. This only persists between calls with `--in-process`
+int32_t count(const uint8_t *input, size_t input_length, uint8_t **output, size_t *output_length) {
+	(void)input;
+	(void)input_length;
+	static int calls = 0;
+	calls++;
+	char *buffer = malloc(16);
+	if (buffer == NULL) {
+		return 1;
+	}
+	*output_length = (size_t)snprintf(buffer, 16, "%d", calls);
+	*output = (uint8_t *)buffer;
+	return 0;
+}
+
+void count_free(uint8_t *output, size_t output_length) {
+	(void)output_length;
+	free(output);
+}
@@ -35,6 +35,13 @@ let buffer = "";
 for await (const line of console) {
 	if (line == "close") break;
 
+	// The preamble of the following cases, which this example does not need
+	if (line == "setup") {
+		console.log("end");
+		buffer = "";
+		continue
+	}
+
 	if (line == "end") {
 		await sendMessage(buffer);
 		console.log("end");
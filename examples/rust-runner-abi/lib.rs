@@ -0,0 +1,48 @@
+use std::ffi::c_void;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Str {
+	pointer: *const u8,
+	length: usize,
+}
+
+impl Str {
+	unsafe fn as_str<'a>(self) -> &'a str {
+		unsafe { std::str::from_utf8_unchecked(std::slice::from_raw_parts(self.pointer, self.length)) }
+	}
+}
+
+#[repr(C)]
+pub struct Input {
+	case: Str,
+	options: Str,
+	name: Str,
+	section: Str,
+	language: Str,
+}
+
+#[repr(C)]
+pub struct Output {
+	context: *mut c_void,
+	write: unsafe extern "C" fn(*mut c_void, Str),
+}
+
+#[unsafe(no_mangle)]
+pub static SPECTRA_ABI_VERSION: u32 = 1;
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn get_result(input: *const Input, output: *mut Output) -> i32 {
+	let (input, output) = unsafe { (&*input, &mut *output) };
+	let (case, options) = unsafe { (input.case.as_str(), input.options.as_str()) };
+
+	let result = if options.contains("lowercase") {
+		case.to_lowercase()
+	} else {
+		case.to_uppercase()
+	};
+
+	let result = Str { pointer: result.as_ptr(), length: result.len() };
+	unsafe { (output.write)(output.context, result) };
+	0
+}
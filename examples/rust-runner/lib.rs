@@ -1,4 +1,31 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 #[unsafe(no_mangle)]
 pub fn get_result(input: &str) -> Result<String, String> {
 	Ok(input.to_uppercase())
-}
\ No newline at end of file
+}
+
+/// The number of calls so far. This only persists between calls with `--in-process`
+#[unsafe(no_mangle)]
+pub fn count(_input: &str) -> Result<String, String> {
+	static CALLS: AtomicUsize = AtomicUsize::new(0);
+	Ok((CALLS.fetch_add(1, Ordering::Relaxed) + 1).to_string())
+}
+
+/// Prints without a newline, which is only written once flushed by `spectra_flush`
+#[unsafe(no_mangle)]
+pub fn shout(input: &str) -> Result<String, String> {
+	print!("shouting");
+	Ok(input.to_uppercase())
+}
+
+#[unsafe(no_mangle)]
+pub fn crash(_input: &str) -> Result<String, String> {
+	panic!("oh no")
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn spectra_flush() {
+	use std::io::Write;
+	let _ = std::io::stdout().flush();
+}
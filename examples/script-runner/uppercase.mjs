@@ -0,0 +1,6 @@
+// Run with `spectra test ./examples/specification.uppercase.md script:examples/script-runner/uppercase.mjs::getResult`
+
+export function getResult(input) {
+	console.log("printed output goes to stderr");
+	return input.toUpperCase();
+}
@@ -0,0 +1,6 @@
+# Run with `spectra test ./examples/specification.uppercase.md script:examples/script-runner/uppercase.py::get_result`
+
+
+def get_result(case: str) -> str:
+    print("printed output goes to stderr")
+    return case.upper()
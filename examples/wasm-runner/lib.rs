@@ -0,0 +1,18 @@
+// Build with `cargo build --target wasm32-unknown-unknown --release`
+// and run with `spectra test ./examples/specification.uppercase.md wasm:examples/wasm-runner/target/wasm32-unknown-unknown/release/wasm_runner.wasm::get_result`
+
+#[unsafe(no_mangle)]
+pub extern "C" fn spectra_alloc(length: usize) -> *mut u8 {
+	Vec::<u8>::with_capacity(length).leak().as_mut_ptr()
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn get_result(input: *const u8, input_length: usize, output: *mut [u32; 2]) -> i32 {
+	let input = unsafe { std::slice::from_raw_parts(input, input_length) };
+	let Ok(input) = str::from_utf8(input) else {
+		return 1;
+	};
+	let result = input.to_uppercase().into_bytes().leak();
+	unsafe { *output = [result.as_ptr() as u32, result.len() as u32] };
+	0
+}
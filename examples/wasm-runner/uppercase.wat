@@ -0,0 +1,31 @@
+;; Uppercases ASCII in place. `spectra_alloc` bumps a pointer from 1024. `spin` never returns
+;; (which fuel stops). Used by the `wasm_runner` test, which compiles it with the `wat` crate
+(module
+    (memory (export "memory") 1)
+    (global $next (mut i32) (i32.const 1024))
+    (func (export "spectra_alloc") (param $length i32) (result i32)
+        (local $pointer i32)
+        (local.set $pointer (global.get $next))
+        (global.set $next (i32.add (global.get $next) (local.get $length)))
+        (local.get $pointer))
+    (func (export "get_result") (param $input i32) (param $length i32) (param $output i32) (result i32)
+        (local $index i32)
+        (local $byte i32)
+        (block $done
+            (loop $next
+                (br_if $done (i32.ge_u (local.get $index) (local.get $length)))
+                (local.set $byte (i32.load8_u (i32.add (local.get $input) (local.get $index))))
+                (if (i32.and
+                        (i32.ge_u (local.get $byte) (i32.const 97))
+                        (i32.le_u (local.get $byte) (i32.const 122)))
+                    (then (i32.store8
+                        (i32.add (local.get $input) (local.get $index))
+                        (i32.sub (local.get $byte) (i32.const 32)))))
+                (local.set $index (i32.add (local.get $index) (i32.const 1)))
+                (br $next)))
+        (i32.store (local.get $output) (local.get $input))
+        (i32.store (i32.add (local.get $output) (i32.const 4)) (local.get $length))
+        (i32.const 0))
+    (func (export "spin") (param i32 i32 i32) (result i32)
+        (loop $forever (br $forever))
+        (i32.const 0)))
@@ -0,0 +1,253 @@
+//! Times each case with a runner (`spectra bench` or `test --bench`). The medians can be saved
+//! and compared against those of an earlier run to find regressions
+//!
+//! Spawning a program takes much longer than most cases, so this is most useful with the
+//! in-process `rust:` runner or `--rpc`
+
+use crate::{RunConfiguration, Runner, Test, TestGroup, TestResults};
+
+use colored::Colorize as Colourise;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct BenchConfiguration {
+    /// runs of each case before measuring
+    pub warmup: usize,
+    pub iterations: usize,
+    /// medians from an earlier run (see [`save`])
+    pub baseline: Option<PathBuf>,
+    /// where to save the medians of this run
+    pub save: Option<PathBuf>,
+    /// a case has regressed if its median is more than this fraction slower than the baseline
+    pub threshold: f64,
+}
+
+impl Default for BenchConfiguration {
+    fn default() -> Self {
+        Self {
+            warmup: 3,
+            iterations: 20,
+            baseline: None,
+            save: None,
+            threshold: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Measurement {
+    pub name: String,
+    pub median: Duration,
+    pub minimum: Duration,
+    /// between the fastest and slowest iteration
+    pub spread: Duration,
+}
+
+impl Measurement {
+    /// # Panics
+    /// if `times` is empty
+    #[must_use]
+    pub fn from_times(name: String, mut times: Vec<Duration>) -> Self {
+        assert!(!times.is_empty(), "no times for {name}");
+        times.sort_unstable();
+
+        let middle = times.len() / 2;
+        let median = if times.len().is_multiple_of(2) {
+            (times[middle - 1] + times[middle]) / 2
+        } else {
+            times[middle]
+        };
+        let minimum = times[0];
+        let spread = times[times.len() - 1].saturating_sub(minimum);
+
+        Self {
+            name,
+            median,
+            minimum,
+            spread,
+        }
+    }
+}
+
+/// Medians by case name
+pub type Baseline = HashMap<String, Duration>;
+
+/// Reads medians written by [`save`]
+///
+/// # Errors
+/// if the file cannot be read or is not an object of nanoseconds
+pub fn read_baseline(path: &Path) -> Result<Baseline, String> {
+    use tinyjson::JsonValue;
+
+    let display = path.display();
+    let content =
+        std::fs::read_to_string(path).map_err(|err| format!("could not read {display}: {err}"))?;
+    let value: JsonValue = content
+        .parse()
+        .map_err(|err| format!("could not parse {display}: {err}"))?;
+    let Some(object) = value.get::<HashMap<String, JsonValue>>() else {
+        return Err(format!("expected {display} to be an object"));
+    };
+
+    let mut baseline = Baseline::new();
+    for (name, value) in object {
+        let Some(nanoseconds) = value.get::<f64>() else {
+            return Err(format!("expected {name:?} in {display} to be a number"));
+        };
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let nanoseconds = *nanoseconds as u64;
+        baseline.insert(name.clone(), Duration::from_nanos(nanoseconds));
+    }
+    Ok(baseline)
+}
+
+/// Writes the medians (in nanoseconds) as a JSON object
+///
+/// # Errors
+/// if the file cannot be written
+pub fn save(path: &Path, measurements: &[Measurement]) -> Result<(), String> {
+    use tinyjson::JsonValue;
+
+    let object: HashMap<String, JsonValue> = measurements
+        .iter()
+        .map(|measurement| {
+            #[allow(clippy::cast_precision_loss)]
+            let nanoseconds = measurement.median.as_nanos() as f64;
+            (measurement.name.clone(), JsonValue::Number(nanoseconds))
+        })
+        .collect();
+    let content = JsonValue::Object(object)
+        .format()
+        .map_err(|err| err.to_string())?;
+
+    std::fs::write(path, content)
+        .map_err(|err| format!("could not write {path}: {err}", path = path.display()))
+}
+
+/// Runs each case which is not skipped or ignored `warmup + iterations` times. Cases which
+/// error, or regress against `baseline`, are failures
+pub fn run_benchmarks(
+    groups: &[TestGroup],
+    runner: &mut impl Runner,
+    configuration: &RunConfiguration,
+    bench: &BenchConfiguration,
+    baseline: &Baseline,
+) -> (TestResults, Vec<Measurement>) {
+    fn run_once(runner: &mut impl Runner, test: &Test) -> Result<(), String> {
+        if test.session {
+            if !runner.keeps_state() {
+                return Err(crate::SESSION_REQUIRES_STATE.to_owned());
+            }
+            for step in test.step_tests() {
+                runner.run(&step)?;
+            }
+            Ok(())
+        } else {
+            runner.run(test).map(|_| ())
+        }
+    }
+
+    let mut results = TestResults::default();
+    let mut measurements = Vec::new();
+    let print = !(configuration.skip_print_test_results || configuration.quiet);
+
+    let cases = groups.iter().flat_map(|group| {
+        let cases = group.cases.iter().enumerate();
+        cases.map(move |(index, test)| (group, index, test))
+    });
+
+    for (group, index, test) in cases {
+        results.count += 1;
+        let name = group.case_name(index);
+
+        // Transcripts are not run by the runner
+        if test.transcript || crate::is_filtered_out(group, &name, test, configuration) {
+            results.skipped += 1;
+            continue;
+        }
+        if test.is_ignored() {
+            results.ignored += 1;
+            continue;
+        }
+
+        let mut times = Vec::with_capacity(bench.iterations);
+        let mut error = None;
+        for iteration in 0..(bench.warmup + bench.iterations) {
+            let start = Instant::now();
+            if let Err(err) = run_once(runner, test) {
+                error = Some(err);
+                break;
+            }
+            if iteration >= bench.warmup {
+                times.push(start.elapsed());
+            }
+        }
+
+        if let Some(err) = error {
+            if print {
+                println!("test {name} ... {result}", result = "fail".red());
+            }
+            results
+                .failures
+                .push((name.into_owned(), String::new(), err));
+            continue;
+        }
+        if times.is_empty() {
+            results.skipped += 1;
+            continue;
+        }
+
+        let measurement = Measurement::from_times(name.into_owned(), times);
+        let Measurement {
+            ref name,
+            median,
+            minimum,
+            spread,
+        } = measurement;
+
+        let regression = baseline.get(name).and_then(|previous| {
+            let change = median.as_secs_f64() / previous.as_secs_f64() - 1.;
+            (change > bench.threshold).then_some((previous, change))
+        });
+
+        if print {
+            print!("test {name} ... bench: {median:?}/iter (min {minimum:?}, +/- {spread:?})");
+            if let Some((_, change)) = regression {
+                let percent = change * 100.;
+                println!(" {}", format!("regressed by {percent:.1}%").red());
+            } else {
+                println!();
+            }
+        }
+
+        if let Some((previous, change)) = regression {
+            let percent = change * 100.;
+            let message =
+                format!("median {median:?} is {percent:.1}% slower than the baseline {previous:?}");
+            results
+                .failures
+                .push((name.clone(), message, String::new()));
+        } else {
+            results.measured += 1;
+        }
+        measurements.push(measurement);
+    }
+
+    (results, measurements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measurement() {
+        let times = [5, 1, 3, 9].map(Duration::from_millis).to_vec();
+        let measurement = Measurement::from_times("a".to_owned(), times);
+        assert_eq!(measurement.median, Duration::from_millis(4));
+        assert_eq!(measurement.minimum, Duration::from_millis(1));
+        assert_eq!(measurement.spread, Duration::from_millis(8));
+    }
+}
@@ -0,0 +1,413 @@
+//! Suites read from `spectra.toml` or the `[package.metadata.spectra]` table of `Cargo.toml`
+//!
+//! ```toml
+//! [suites.uppercase]
+//! pattern = "./examples/specification.uppercase.md"
+//! runner = "cargo run -q --example example_stdin_stdout_program -- --uppercase"
+//! rpc = true
+//! timeout = 1000
+//!
+//! [suites.uppercase.normalise]
+//! trim-trailing-whitespace = true
+//! ```
+
+use crate::runners::program::CommandConfiguration;
+use crate::utilities::{Normalisation, filter};
+
+use std::time::Duration;
+
+pub const FILE_NAME: &str = "spectra.toml";
+
+#[derive(Debug, Default, Clone)]
+pub struct Suite {
+    /// glob path to markdown files
+    pub pattern: String,
+    /// either a program or `rust:*path*::*function*`
+    pub runner: String,
+    pub command: CommandConfiguration,
+    pub lists_as_expected: bool,
+    pub interactive: bool,
+    pub only: Option<String>,
+    pub skip: Option<String>,
+    pub case_sensitive: bool,
+    pub normalisation: Normalisation,
+    pub quiet: bool,
+    pub report_extra_files: bool,
+    pub tags: Vec<String>,
+    pub skip_tags: Vec<String>,
+}
+
+impl Suite {
+    #[must_use]
+    pub fn filter(&self) -> Option<filter::GlobPattern> {
+        let (matcher, positive) = match (&self.only, &self.skip) {
+            (Some(only), _) => (only, true),
+            (None, Some(skip)) => (skip, false),
+            (None, None) => return None,
+        };
+        Some(filter::GlobPattern {
+            matcher: glob::Pattern::new(matcher).ok()?,
+            positive,
+            case_sensitive: self.case_sensitive,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Configuration {
+    pub suites: Vec<(String, Suite)>,
+}
+
+impl Configuration {
+    /// Looks for `spectra.toml` and then `Cargo.toml` in the current directory
+    ///
+    /// # Errors
+    /// if a file exists but is not valid
+    pub fn find() -> Result<Option<Self>, String> {
+        if let Ok(content) = std::fs::read_to_string(FILE_NAME) {
+            let table: toml::Table = content
+                .parse()
+                .map_err(|err| format!("could not parse {FILE_NAME}: {err}"))?;
+            return Self::from_table(&table).map(Some);
+        }
+
+        if let Ok(content) = std::fs::read_to_string("Cargo.toml") {
+            let table: toml::Table = content
+                .parse()
+                .map_err(|err| format!("could not parse Cargo.toml: {err}"))?;
+
+            let spectra = table
+                .get("package")
+                .and_then(|package| package.get("metadata"))
+                .and_then(|metadata| metadata.get("spectra"))
+                .and_then(toml::Value::as_table);
+
+            if let Some(spectra) = spectra {
+                return Self::from_table(spectra).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// # Errors
+    /// if there are unknown keys or values of the wrong type
+    pub fn from_table(table: &toml::Table) -> Result<Self, String> {
+        let mut suites = Vec::new();
+
+        for (key, value) in table {
+            match key.as_str() {
+                "suites" => {
+                    let Some(items) = value.as_table() else {
+                        return Err("expected 'suites' to be a table".to_owned());
+                    };
+                    for (name, suite) in items {
+                        let Some(suite) = suite.as_table() else {
+                            return Err(format!("expected suite '{name}' to be a table"));
+                        };
+                        let suite = suite_from_table(suite)
+                            .map_err(|err| format!("in suite '{name}': {err}"))?;
+                        suites.push((name.clone(), suite));
+                    }
+                }
+                key => return Err(format!("unknown key '{key}'")),
+            }
+        }
+
+        Ok(Self { suites })
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Suite> {
+        self.suites
+            .iter()
+            .find_map(|(suite_name, suite)| (suite_name == name).then_some(suite))
+    }
+}
+
+fn suite_from_table(table: &toml::Table) -> Result<Suite, String> {
+    fn string(key: &str, value: &toml::Value) -> Result<String, String> {
+        value
+            .as_str()
+            .map(str::to_owned)
+            .ok_or_else(|| format!("expected '{key}' to be a string"))
+    }
+
+    fn boolean(key: &str, value: &toml::Value) -> Result<bool, String> {
+        value
+            .as_bool()
+            .ok_or_else(|| format!("expected '{key}' to be a boolean"))
+    }
+
+    /// milliseconds or a string such as `"5s"`
+    fn duration(key: &str, value: &toml::Value) -> Result<Duration, String> {
+        let duration = if let Some(value) = value.as_str() {
+            crate::utilities::parse_duration(value)
+        } else {
+            value
+                .as_integer()
+                .and_then(|value| u64::try_from(value).ok())
+                .map(Duration::from_millis)
+        };
+        duration.ok_or_else(|| format!("expected '{key}' to be a duration"))
+    }
+
+    fn number(key: &str, value: &toml::Value) -> Result<u64, String> {
+        value
+            .as_integer()
+            .and_then(|value| u64::try_from(value).ok())
+            .ok_or_else(|| format!("expected '{key}' to be a positive number"))
+    }
+
+    /// bytes or a string such as `"512MB"`
+    fn size(key: &str, value: &toml::Value) -> Result<u64, String> {
+        if let Some(value) = value.as_str() {
+            crate::utilities::parse_size(value)
+                .ok_or_else(|| format!("expected '{key}' to be a size"))
+        } else {
+            number(key, value)
+        }
+    }
+
+    let mut suite = Suite::default();
+    let mut has_pattern = false;
+    let mut has_runner = false;
+    // only passed on to programs (and `script:`)
+    let mut program_keys = Vec::new();
+
+    for (key, value) in table {
+        if matches!(
+            key.as_str(),
+            "rpc" | "ignore-exit-code" | "startup-timeout" | "limits" | "pty" | "keep-ansi"
+        ) {
+            program_keys.push(key.as_str());
+        }
+        match key.as_str() {
+            "pattern" => {
+                suite.pattern = string(key, value)?;
+                has_pattern = true;
+            }
+            "runner" => {
+                suite.runner = string(key, value)?;
+                has_runner = true;
+            }
+            "rpc" => suite.command.stdin_stdout_communication = boolean(key, value)?,
+            "ignore-exit-code" => suite.command.ignore_exit_code = boolean(key, value)?,
+            "timeout" => suite.command.timeout = Some(duration(key, value)?),
+            "startup-timeout" => suite.command.startup_timeout = Some(duration(key, value)?),
+            "limits" => {
+                let Some(limits) = value.as_table() else {
+                    return Err(format!("expected '{key}' to be a table"));
+                };
+                for (key, value) in limits {
+                    let limits = &mut suite.command.limits;
+                    match key.as_str() {
+                        "memory" => limits.address_space = Some(size(key, value)?),
+                        "cpu" => limits.cpu_seconds = Some(number(key, value)?),
+                        "files" => limits.open_files = Some(number(key, value)?),
+                        "processes" => limits.processes = Some(number(key, value)?),
+                        "output" => {
+                            let output = usize::try_from(size(key, value)?)
+                                .map_err(|_| format!("'{key}' is too large"))?;
+                            limits.output = Some(output);
+                        }
+                        key => return Err(format!("unknown limit '{key}'")),
+                    }
+                }
+            }
+            "pty" => {
+                suite.command.pty = if let Some(size) = value.as_str() {
+                    let size = size.parse().map_err(|()| {
+                        format!("expected '{key}' to be in the form \"*columns*x*rows*\"")
+                    })?;
+                    Some(size)
+                } else {
+                    boolean(key, value)?.then(Default::default)
+                };
+            }
+            "keep-ansi" => suite.command.keep_ansi = boolean(key, value)?,
+            "lists-as-expected" => suite.lists_as_expected = boolean(key, value)?,
+            "interactive" => suite.interactive = boolean(key, value)?,
+            "report-extra-files" => suite.report_extra_files = boolean(key, value)?,
+            "only" | "skip" => {
+                let pattern = string(key, value)?;
+                if glob::Pattern::new(&pattern).is_err() {
+                    return Err(format!("expected '{key}' to be a valid glob"));
+                }
+                if key == "only" {
+                    suite.only = Some(pattern);
+                } else {
+                    suite.skip = Some(pattern);
+                }
+            }
+            "case-sensitive" => suite.case_sensitive = boolean(key, value)?,
+            "tags" | "skip-tags" => {
+                let Some(items) = value.as_array() else {
+                    return Err(format!("expected '{key}' to be an array"));
+                };
+                let tags = if key == "tags" {
+                    &mut suite.tags
+                } else {
+                    &mut suite.skip_tags
+                };
+                for item in items {
+                    tags.push(string(key, item)?);
+                }
+            }
+            "reporter" => match value.as_str() {
+                Some("default") => suite.quiet = false,
+                Some("quiet") => suite.quiet = true,
+                _ => return Err("expected 'reporter' to be \"default\" or \"quiet\"".to_owned()),
+            },
+            "normalise" | "normalize" => {
+                let Some(normalise) = value.as_table() else {
+                    return Err(format!("expected '{key}' to be a table"));
+                };
+                for (key, value) in normalise {
+                    match key.as_str() {
+                        "trim-trailing-whitespace" => {
+                            suite.normalisation.trim_trailing_whitespace = boolean(key, value)?;
+                        }
+                        "strip-lines-ending-with" => {
+                            let Some(items) = value.as_array() else {
+                                return Err(format!("expected '{key}' to be an array"));
+                            };
+                            for item in items {
+                                let item = string(key, item)?;
+                                suite.normalisation.strip_lines_ending_with.push(item);
+                            }
+                        }
+                        key => return Err(format!("unknown normalisation '{key}'")),
+                    }
+                }
+            }
+            key => return Err(format!("unknown key '{key}'")),
+        }
+    }
+
+    if !has_pattern {
+        return Err("missing 'pattern'".to_owned());
+    }
+    if !has_runner {
+        return Err("missing 'runner'".to_owned());
+    }
+    if suite.only.is_some() && suite.skip.is_some() {
+        return Err("'only' and 'skip' cannot both be set".to_owned());
+    }
+    if let Some(prefix) = ["rust:", "wasm:", "c:"]
+        .into_iter()
+        .find(|prefix| suite.runner.starts_with(prefix))
+    {
+        if prefix == "c:" && suite.command.timeout.is_some() {
+            program_keys.push("timeout");
+        }
+        if let Some(key) = program_keys.first() {
+            return Err(format!("'{key}' is not supported by the `{prefix}` runner"));
+        }
+    }
+
+    Ok(suite)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suites() {
+        let table: toml::Table = r#"
+            [suites.uppercase]
+            pattern = "./examples/specification.uppercase.md"
+            runner = "bun run examples/example_stdin_stdout_program.js --uppercase"
+            rpc = true
+            timeout = 1000
+            startup-timeout = "20s"
+            skip = "other"
+
+            [suites.uppercase.normalise]
+            trim-trailing-whitespace = true
+
+            [suites.uppercase.limits]
+            memory = "512MB"
+            cpu = 10
+        "#
+        .parse()
+        .unwrap();
+
+        let configuration = Configuration::from_table(&table).unwrap();
+        let suite = configuration.get("uppercase").unwrap();
+
+        assert_eq!(suite.pattern, "./examples/specification.uppercase.md");
+        assert!(suite.command.stdin_stdout_communication);
+        assert_eq!(suite.command.timeout, Some(Duration::from_secs(1)));
+        assert_eq!(suite.command.startup_timeout, Some(Duration::from_secs(20)));
+        assert!(suite.normalisation.trim_trailing_whitespace);
+        assert_eq!(suite.command.limits.address_space, Some(512 * 1024 * 1024));
+        assert_eq!(suite.command.limits.cpu_seconds, Some(10));
+        assert!(suite.filter().is_some_and(|filter| !filter.positive));
+        assert!(configuration.get("other").is_none());
+    }
+
+    #[test]
+    fn unknown_key() {
+        let table: toml::Table = r#"
+            [suites.a]
+            pattern = "*.md"
+            runner = "cat"
+            colour = true
+        "#
+        .parse()
+        .unwrap();
+
+        assert!(Configuration::from_table(&table).is_err());
+    }
+
+    #[test]
+    fn invalid_glob() {
+        let table: toml::Table = r#"
+            [suites.a]
+            pattern = "*.md"
+            runner = "cat"
+            only = "[a"
+        "#
+        .parse()
+        .unwrap();
+
+        let err = Configuration::from_table(&table).unwrap_err();
+        assert_eq!(err, "in suite 'a': expected 'only' to be a valid glob");
+    }
+
+    #[test]
+    fn only_and_skip() {
+        let table: toml::Table = r#"
+            [suites.a]
+            pattern = "*.md"
+            runner = "cat"
+            only = "a*"
+            skip = "b*"
+        "#
+        .parse()
+        .unwrap();
+
+        let err = Configuration::from_table(&table).unwrap_err();
+        assert_eq!(err, "in suite 'a': 'only' and 'skip' cannot both be set");
+    }
+
+    #[test]
+    fn program_keys_on_compiled_runner() {
+        let table: toml::Table = r#"
+            [suites.a]
+            pattern = "*.md"
+            runner = "rust:lib.so::run"
+            timeout = 1000
+
+            [suites.a.limits]
+            memory = "512MB"
+        "#
+        .parse()
+        .unwrap();
+
+        let err = Configuration::from_table(&table).unwrap_err();
+        assert_eq!(err, "in suite 'a': 'limits' is not supported by the `rust:` runner");
+    }
+}
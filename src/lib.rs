@@ -1,21 +1,132 @@
+pub mod bench;
+pub mod configuration;
 pub mod runners;
+pub mod transcript;
 pub mod utilities;
 
-use utilities::{filter, is_equal_ignore_new_line_sequence, run_in_alternative_display};
+use utilities::{
+    Normalisation, filter, is_equal_ignore_new_line_sequence, run_in_alternative_display,
+};
 
 use colored::Colorize as Colourise;
 use std::io;
 
-/// TODO vec of vecs
-#[derive(Debug, Default)]
+/// A single case (see [`TestGroup`])
+#[derive(Debug, Default, Clone)]
 pub struct Test {
     pub section: String,
     pub name: String,
     pub options: String,
     pub case: String,
+    /// of the code block the case was taken from
+    pub language: String,
     pub expected: Option<String>,
     pub command: bool,
     pub merge_stderr: bool,
+    /// overrides the timeout of the runner
+    pub timeout: Option<std::time::Duration>,
+    /// `case` is a shell transcript which is run by spectra rather than the runner (see
+    /// [`transcript`])
+    pub transcript: bool,
+    /// `(path, content)` of code blocks with a `title=*path*` in their info string. These
+    /// are written to a new directory before the test is run
+    pub files: Vec<(String, String)>,
+    /// `(path, content)` of code blocks with an `output=*path*` in their info string. After
+    /// the run, these are compared with the files the test wrote (see [`Runner::directory`])
+    pub expected_files: Vec<(String, String)>,
+    /// started with `> Session`. Rather than `case` and `expected`, the test is made up of
+    /// `steps` which are run one after another on the same runner
+    pub session: bool,
+    /// input and expected output for each step of a session
+    pub steps: Vec<(String, Option<String>)>,
+    /// from a `> Setup` block in the section. `case` starts with it, but in `--rpc` mode it
+    /// is sent once rather than with every case
+    pub setup: String,
+    /// from `{#tag}` and `(attribute)` at the end of the heading or a `> Tags` quote (see
+    /// [`ATTRIBUTES`])
+    pub tags: Vec<String>,
+    /// identifies the case in its group (see [`TestGroup::case_name`]), such as the input of
+    /// a table row
+    pub label: Option<String>,
+}
+
+/// The cases under a heading
+#[derive(Debug, Default)]
+pub struct TestGroup {
+    pub section: String,
+    pub name: String,
+    pub cases: Vec<Test>,
+    /// the file the group was read from, if it was included (see [`Input::includes`])
+    pub source: Option<std::path::PathBuf>,
+}
+
+impl TestGroup {
+    /// `*name* [*label*]` for labelled cases, else `*name* [case *n*/*total*]` if there is
+    /// more than one case
+    #[must_use]
+    pub fn case_name(&self, index: usize) -> std::borrow::Cow<'_, str> {
+        let total = self.cases.len();
+        if let Some(ref label) = self.cases[index].label {
+            let name = &self.name;
+            format!("{name} [{label}]").into()
+        } else if total == 1 {
+            self.name.as_str().into()
+        } else {
+            let name = &self.name;
+            let number = index + 1;
+            format!("{name} [case {number}/{total}]").into()
+        }
+    }
+}
+
+/// Attributes which can be in parentheses at the end of a heading, such as `(ignore)`
+pub const ATTRIBUTES: &[&str] = &[
+    "skip",
+    "ignore",
+    "only-linux",
+    "only-macos",
+    "only-windows",
+    "only-unix",
+    "xfail",
+];
+
+impl Test {
+    /// From `(ignore)` (or `(skip)`) or an `only-*` attribute (in [`ATTRIBUTES`]) for another
+    /// platform. Other tags starting with `only-` are not platforms
+    #[must_use]
+    pub fn is_ignored(&self) -> bool {
+        self.tags.iter().any(|tag| match tag.as_str() {
+            "skip" | "ignore" => true,
+            "only-unix" => !cfg!(unix),
+            "only-linux" => std::env::consts::OS != "linux",
+            "only-macos" => std::env::consts::OS != "macos",
+            "only-windows" => std::env::consts::OS != "windows",
+            _ => false,
+        })
+    }
+
+    /// From `(xfail)`. The test passes while it fails and fails once it passes
+    #[must_use]
+    pub fn is_expected_to_fail(&self) -> bool {
+        self.tags.iter().any(|tag| tag == "xfail")
+    }
+
+    /// A test for each step of a session
+    pub fn step_tests(&self) -> impl Iterator<Item = Test> + '_ {
+        self.steps.iter().map(|(case, expected)| Test {
+            section: self.section.clone(),
+            name: self.name.clone(),
+            options: self.options.clone(),
+            case: case.clone(),
+            language: self.language.clone(),
+            expected: expected.clone(),
+            merge_stderr: self.merge_stderr,
+            timeout: self.timeout,
+            setup: self.setup.clone(),
+            tags: self.tags.clone(),
+            ..Test::default()
+        })
+    }
 }
 
 pub trait Runner: Sized {
@@ -25,10 +136,36 @@ pub trait Runner: Sized {
     /// if test failed on runner, return a `Err` with some message about why it failed
     fn run(&mut self, test: &Test) -> Result<(String, String), String>;
 
+    /// The directory the last test was run with, for reading the files it wrote
+    fn directory(&self) -> Option<&std::path::Path> {
+        None
+    }
+
+    /// Whether state carries over from one call of [`Runner::run`] to the next, as required by
+    /// the steps of a session
+    fn keeps_state(&self) -> bool {
+        true
+    }
+
     /// Cleanup
     fn close(self) {}
 }
 
+/// Does not close the runner, so that it can be used again
+impl<T: Runner> Runner for &mut T {
+    fn run(&mut self, test: &Test) -> Result<(String, String), String> {
+        (**self).run(test)
+    }
+
+    fn directory(&self) -> Option<&std::path::Path> {
+        (**self).directory()
+    }
+
+    fn keeps_state(&self) -> bool {
+        (**self).keeps_state()
+    }
+}
+
 #[derive(Default)]
 pub struct RunConfiguration {
     pub interactive: bool,
@@ -37,74 +174,239 @@ pub struct RunConfiguration {
     pub no_colors: bool,
     pub filter: Option<Box<dyn filter::Filter>>,
     pub skip_print_test_results: bool,
+    /// only print failures and the summary
+    pub quiet: bool,
+    pub normalisation: Normalisation,
+    /// only run tests with one of these tags
+    pub tags: Vec<String>,
+    /// skip tests with one of these tags
+    pub skip_tags: Vec<String>,
+    /// report files written by a test which are neither [`Test::files`] nor
+    /// [`Test::expected_files`]
+    pub report_extra_files: bool,
+    /// time cases rather than check their output
+    pub bench: Option<bench::BenchConfiguration>,
 }
 
 pub struct Input {
-    pub tests: Vec<Test>,
+    pub groups: Vec<TestGroup>,
     pub expected_runner: Option<String>,
+    /// `(index into groups, path)` from the `include` frontmatter key and
+    /// `<!-- include: *path* -->` comments. These are resolved by [`extract_tests_from_file`]
+    pub includes: Vec<(usize, String)>,
+}
+
+impl Input {
+    /// The cases of every group
+    pub fn tests(&self) -> impl Iterator<Item = &Test> {
+        self.groups.iter().flat_map(|group| &group.cases)
+    }
 }
 
 #[must_use]
 pub fn extract_tests(content: &str, lists_to_code_block: bool) -> Input {
     use simple_markdown_parser::{CodeBlock, MarkdownElement, QuoteBlock, parse};
 
-    fn add_one(on: &str) -> String {
-        let on = on.trim_end();
-        if let Some((before, after)) = on.rsplit_once('(')
-            && let Some(maybe_number) = after.strip_suffix(')')
-            && let Ok(value) = <usize as std::str::FromStr>::from_str(maybe_number)
-        {
-            let before = before.trim_end();
-            let next = value + 1;
-            format!("{before} ({next})")
+    /// Adds to the last group if `test` is a further case of it. With `parameters`, a case
+    /// is added for each set of values
+    fn add_to_groups(
+        groups: &mut Vec<TestGroup>,
+        mut test: Test,
+        another_case: bool,
+        parameters: &[Vec<(String, String)>],
+    ) {
+        if !parameters.is_empty() {
+            for (index, values) in parameters.iter().enumerate() {
+                let substitute = |on: &str| {
+                    let mut on = on.to_owned();
+                    for (name, value) in values {
+                        on = on.replace(&format!("{{{{{name}}}}}"), value);
+                    }
+                    on
+                };
+                let mut label: Vec<String> = values
+                    .iter()
+                    .map(|(name, value)| format!("{name}={value}"))
+                    .collect();
+                if let Some(ref existing) = test.label {
+                    label.insert(0, existing.clone());
+                }
+
+                let case = Test {
+                    case: substitute(&test.case),
+                    expected: test.expected.as_deref().map(substitute),
+                    options: substitute(&test.options),
+                    label: Some(label.join(", ")),
+                    ..test.clone()
+                };
+                add_to_groups(groups, case, another_case || index > 0, &[]);
+            }
+            return;
+        }
+
+        // For sessions, the setup is part of the first step
+        if !(test.setup.is_empty() || test.transcript) {
+            let setup = &test.setup;
+            if test.session {
+                if let Some((case, _)) = test.steps.first_mut() {
+                    *case = format!("{setup}\n{case}");
+                }
+            } else {
+                test.case = format!("{setup}\n{}", test.case);
+            }
+        }
+
+        if another_case && let Some(group) = groups.last_mut() {
+            group.cases.push(test);
         } else {
-            let initial = 1;
-            format!("{on} ({initial})")
+            groups.push(TestGroup {
+                section: test.section.clone(),
+                name: test.name.clone(),
+                cases: vec![test],
+                source: None,
+            });
+        }
+    }
+
+    /// Splits `{#slow #regression}` and `(ignore, only-linux)` from the end of a heading.
+    /// Parentheses are only taken if every item is one of [`ATTRIBUTES`]
+    fn split_tags(heading: &str) -> (&str, Vec<String>) {
+        fn items(list: &str) -> impl Iterator<Item = &str> {
+            list.split([',', ' ']).filter(|item| !item.is_empty())
+        }
+
+        let mut name = heading.trim_end();
+        let mut tags = Vec::new();
+        loop {
+            let (open, braces) = match name.chars().last() {
+                Some('}') => ('{', true),
+                Some(')') => ('(', false),
+                _ => break,
+            };
+            let Some(start) = name.rfind(open) else {
+                break;
+            };
+            let list = &name[start + 1..name.len() - 1];
+            let valid = |item: &str| {
+                if braces {
+                    item.starts_with('#')
+                } else {
+                    ATTRIBUTES.contains(&item)
+                }
+            };
+            if items(list).next().is_none() || !items(list).all(valid) {
+                break;
+            }
+            let new = items(list).map(|item| item.trim_start_matches('#').to_owned());
+            tags.splice(0..0, new);
+            name = name[..start].trim_end();
         }
+        (name, tags)
     }
 
-    let mut tests: Vec<Test> = Vec::new();
+    /// The path of a `<!-- include: *path* -->` comment
+    fn include_directive(html: &str) -> Option<&str> {
+        let inner = html.trim().strip_prefix("<!--")?.strip_suffix("-->")?;
+        let path = inner.trim().strip_prefix("include:")?;
+        Some(path.trim())
+    }
+
+    /// Splits an info string such as `ts title=src/a.ts` into the language and the rest
+    fn split_info_string(info: &str) -> (&str, &str) {
+        let info = info.trim();
+        info.split_once(char::is_whitespace)
+            .map_or((info, ""), |(language, attributes)| {
+                (language, attributes.trim_start())
+            })
+    }
+
+    /// Removes the backticks around inline code and unescapes `\|`
+    fn cell_text(cell: &str) -> String {
+        let cell = cell.trim();
+        let cell = cell
+            .strip_prefix('`')
+            .and_then(|cell| cell.strip_suffix('`'))
+            .unwrap_or(cell);
+        cell.replace("\\|", "|")
+    }
+
+    fn attribute<'a>(attributes: &'a str, key: &str) -> Option<&'a str> {
+        attributes.split_whitespace().find_map(|attribute| {
+            let value = attribute.strip_prefix(key)?.strip_prefix('=')?;
+            Some(value.trim_matches('"'))
+        })
+    }
+
+    let mut groups: Vec<TestGroup> = Vec::new();
+    let mut includes: Vec<(usize, String)> = Vec::new();
     let mut current_test = Test::default();
+    // whether `current_test` is a further case under the same heading
+    let mut another_case = false;
     let mut section = String::new();
+    // from `> Setup`, for the tests in the current section
+    let mut setup = String::new();
+    let mut last_was_setup = false;
 
     let mut expected_runner = None;
+    let mut default_timeout = None;
     // let mut total_options = String::new();
 
     let mut last_was_with = false;
+    let mut last_was_with_each = false;
+    // from `With each`, for the cases under the current heading
+    let mut parameters: Vec<Vec<(String, String)>> = Vec::new();
 
     let result = parse::<()>(content, |element| {
         let mut is_with = false;
+        let mut is_with_each = false;
+        let mut is_setup = false;
 
         let add_new = if let MarkdownElement::Heading { level, .. } = element {
             level >= 3
-        } else if let MarkdownElement::CodeBlock(_) = element {
+        } else if let MarkdownElement::CodeBlock(CodeBlock { language, .. }) = &element {
+            // Files and steps are part of the current test
+            let (_, attributes) = split_info_string(language);
             current_test.expected.is_some()
+                && !current_test.session
+                && attribute(attributes, "title").is_none()
+                && attribute(attributes, "output").is_none()
+        } else if let MarkdownElement::HTMLElement(html) = element {
+            // Included tests come after the current test
+            include_directive(html).is_some()
         } else {
             false
         };
 
-        if add_new && !current_test.case.is_empty() {
+        if add_new && !(current_test.case.is_empty() && current_test.steps.is_empty()) {
             let mut test = std::mem::take(&mut current_test);
-            if test.name.ends_with("(skip)") {
-                return Ok(());
-            }
-            if let MarkdownElement::CodeBlock(_) = element {
-                test.name = add_one(&test.name);
-                // TODO could this be better?
-                current_test.name = add_one(&test.name);
+            let is_case = matches!(element, MarkdownElement::CodeBlock(_));
+            if is_case {
+                test.name.clone_into(&mut current_test.name);
+                test.tags.clone_into(&mut current_test.tags);
+                test.section.clone_into(&mut current_test.section);
+                test.setup.clone_into(&mut current_test.setup);
+                current_test.timeout = test.timeout;
             }
 
-            tests.push(test);
+            test.timeout = test.timeout.or(default_timeout);
+            add_to_groups(&mut groups, test, another_case, &parameters);
+            another_case = is_case;
         }
 
         match element {
             MarkdownElement::Heading { level, content } => {
                 // TODO
                 if level >= 3 {
-                    current_test.name = content.0.to_owned(); //.no_decoration();
+                    let (name, tags) = split_tags(content.0);
+                    current_test.name = name.to_owned(); //.no_decoration();
+                    current_test.tags = tags;
                     section.clone_into(&mut current_test.section);
+                    setup.clone_into(&mut current_test.setup);
+                    another_case = false;
+                    parameters.clear();
                 } else {
                     section = content.0.to_owned(); // .no_decoration();
+                    setup.clear();
                 }
             }
             MarkdownElement::Paragraph(content) => {
@@ -114,6 +416,7 @@ pub fn extract_tests(content: &str, lists_to_code_block: bool) -> Input {
                     options.clone_into(&mut current_test.options);
                 } else {
                     is_with = content.0 == "With";
+                    is_with_each = content.0 == "With each";
                 }
             }
             MarkdownElement::List(list) if lists_to_code_block => {
@@ -124,29 +427,173 @@ pub fn extract_tests(content: &str, lists_to_code_block: bool) -> Input {
                     let _ = current_test.expected.insert(content);
                 }
             }
-            MarkdownElement::CodeBlock(CodeBlock { raw_code, .. }) => {
+            MarkdownElement::CodeBlock(CodeBlock {
+                raw_code, language, ..
+            }) => {
+                if last_was_setup {
+                    // Applies to the following tests in the section
+                    raw_code.clone_into(&mut setup);
+                    last_was_setup = false;
+                    return Ok(());
+                }
                 if current_test.name.is_empty() {
                     return Ok(());
                 }
 
-                if last_was_with {
+                let (language, attributes) = split_info_string(language);
+
+                if last_was_with_each {
+                    let result = simple_yaml_parser::parse(raw_code, |keys, value| {
+                        use simple_yaml_parser::{RootYAMLValue, YAMLKey};
+
+                        if let [YAMLKey::Index(index), YAMLKey::Slice(name)] = keys {
+                            let value = match value {
+                                RootYAMLValue::String(value) | RootYAMLValue::Number(value) => {
+                                    value.to_owned()
+                                }
+                                RootYAMLValue::Boolean(value) => value.to_string(),
+                                RootYAMLValue::Null => String::new(),
+                            };
+                            if parameters.len() <= *index {
+                                parameters.resize_with(index + 1, Vec::new);
+                            }
+                            parameters[*index].push(((*name).to_owned(), value));
+                        } else {
+                            eprintln!("expected a list of values, found {keys:?}");
+                        }
+                    });
+                    if let Err(err) = result {
+                        eprintln!("{err:?}");
+                    }
+                } else if let Some(path) = attribute(attributes, "title") {
+                    current_test
+                        .files
+                        .push((path.to_owned(), raw_code.to_owned()));
+                } else if let Some(path) = attribute(attributes, "output") {
+                    current_test
+                        .expected_files
+                        .push((path.to_owned(), raw_code.to_owned()));
+                } else if last_was_with {
                     raw_code.clone_into(&mut current_test.options);
+                } else if current_test.session {
+                    if let Some((_, expected @ None)) = current_test.steps.last_mut() {
+                        *expected = Some(raw_code.to_owned());
+                    } else {
+                        current_test.steps.push((raw_code.to_owned(), None));
+                        language.clone_into(&mut current_test.language);
+                    }
+                } else if transcript::LANGUAGES.contains(&language) && current_test.case.is_empty()
+                {
+                    raw_code.clone_into(&mut current_test.case);
+                    language.clone_into(&mut current_test.language);
+                    current_test.transcript = true;
+                    // Expected output is in the transcript
+                    current_test.expected = Some(String::new());
                 } else if current_test.case.is_empty() {
                     raw_code.clone_into(&mut current_test.case);
+                    language.clone_into(&mut current_test.language);
                 } else if current_test.expected.is_none() {
                     let _ = current_test.expected.insert(raw_code.to_owned());
                 }
             }
+            MarkdownElement::Table(table) => {
+                if current_test.name.is_empty() {
+                    return Ok(());
+                }
+
+                let column = |name: &str| {
+                    let mut header = table.header_row.iter();
+                    header.position(|cell| cell_text(cell.0).eq_ignore_ascii_case(name))
+                };
+
+                if last_was_with_each {
+                    let names: Vec<String> = table
+                        .header_row
+                        .iter()
+                        .map(|cell| cell_text(cell.0))
+                        .collect();
+                    for row in &table.rows {
+                        let values = row.iter().map(|cell| cell_text(cell.0));
+                        parameters.push(names.iter().cloned().zip(values).collect());
+                    }
+                } else if let (Some(input), Some(expected)) = (column("input"), column("expected"))
+                {
+                    let options = column("options");
+                    let label = column("name");
+
+                    // Each row is a further case
+                    if !(current_test.case.is_empty() && current_test.steps.is_empty()) {
+                        let mut test = Test {
+                            name: current_test.name.clone(),
+                            section: current_test.section.clone(),
+                            options: current_test.options.clone(),
+                            merge_stderr: current_test.merge_stderr,
+                            tags: current_test.tags.clone(),
+                            setup: current_test.setup.clone(),
+                            timeout: current_test.timeout,
+                            ..Test::default()
+                        };
+                        std::mem::swap(&mut test, &mut current_test);
+                        test.timeout = test.timeout.or(default_timeout);
+                        add_to_groups(&mut groups, test, another_case, &parameters);
+                        another_case = true;
+                    }
+
+                    for row in &table.rows {
+                        let cell = |index: usize| row.get(index).map(|cell| cell_text(cell.0));
+                        let case = cell(input).unwrap_or_default();
+                        let test = Test {
+                            section: current_test.section.clone(),
+                            name: current_test.name.clone(),
+                            options: options
+                                .and_then(cell)
+                                .unwrap_or_else(|| current_test.options.clone()),
+                            label: Some(label.and_then(cell).unwrap_or_else(|| case.clone())),
+                            case,
+                            expected: cell(expected),
+                            merge_stderr: current_test.merge_stderr,
+                            setup: current_test.setup.clone(),
+                            tags: current_test.tags.clone(),
+                            timeout: current_test.timeout.or(default_timeout),
+                            ..Test::default()
+                        };
+                        add_to_groups(&mut groups, test, another_case, &parameters);
+                        another_case = true;
+                    }
+                }
+            }
             MarkdownElement::Quote(QuoteBlock { inner, .. }) => {
-                if inner.0.trim() == "> Merge `stderr` here" {
+                let inner = inner.0.trim();
+                if inner == "> Merge `stderr` here" {
                     current_test.merge_stderr = true;
+                } else if inner == "> Session" {
+                    current_test.session = true;
+                } else if inner == "> Setup" {
+                    is_setup = true;
+                } else if let Some(tags) = inner.strip_prefix("> Tags ") {
+                    let tags = tags.split([',', ' ']).filter(|tag| !tag.is_empty());
+                    let tags = tags.map(|tag| tag.trim_start_matches('#').to_owned());
+                    current_test.tags.extend(tags);
+                } else if let Some(timeout) = inner.strip_prefix("> Timeout ") {
+                    if let Some(timeout) = utilities::parse_duration(timeout) {
+                        current_test.timeout = Some(timeout);
+                    } else {
+                        eprintln!("skipping invalid timeout {inner:?}");
+                    }
                 }
             }
             MarkdownElement::Frontmatter(frontmatter) => {
                 let result = frontmatter.parse_yaml(|keys, value| {
-                    use simple_yaml_parser::YAMLKey::Slice;
+                    use simple_yaml_parser::YAMLKey::{Index, Slice};
 
                     match keys {
+                        [Slice("include")] | [Slice("include"), Index(_)] => {
+                            if let simple_yaml_parser::RootYAMLValue::String(value) = value {
+                                includes.push((groups.len(), value.to_owned()));
+                            } else {
+                                eprintln!("skipping invalid include {value:?}");
+                            }
+                        }
                         [Slice("expected_runner")] => {
                             if let simple_yaml_parser::RootYAMLValue::String(value) = value {
                                 // TODO this will be different in future right?
@@ -155,6 +602,15 @@ pub fn extract_tests(content: &str, lists_to_code_block: bool) -> Input {
                                 panic!("expected runner to be string")
                             }
                         }
+                        [Slice("timeout")] => {
+                            if let simple_yaml_parser::RootYAMLValue::String(value) = value
+                                && let Some(timeout) = utilities::parse_duration(value)
+                            {
+                                default_timeout = Some(timeout);
+                            } else {
+                                eprintln!("skipping invalid timeout {value:?}");
+                            }
+                        }
                         [Slice("include-language-as-option")] => {
                             // prepends *language*\n---\n
                             todo!();
@@ -172,28 +628,114 @@ pub fn extract_tests(content: &str, lists_to_code_block: bool) -> Input {
                     eprintln!("{err:?}");
                 }
             }
+            MarkdownElement::HTMLElement(html) => {
+                if let Some(path) = include_directive(html) {
+                    includes.push((groups.len(), path.to_owned()));
+                    another_case = false;
+                }
+            }
             _ => {}
         }
 
         last_was_with = is_with;
+        last_was_with_each = is_with_each;
+        last_was_setup = is_setup;
         Ok(())
     });
 
     assert!(result.is_ok(), "{result:?}");
-    if !(current_test.case.is_empty() || current_test.name.ends_with("(skip)")) {
-        tests.push(current_test);
+    let is_empty = current_test.case.is_empty() && current_test.steps.is_empty();
+    if !is_empty {
+        current_test.timeout = current_test.timeout.or(default_timeout);
+        add_to_groups(&mut groups, current_test, another_case, &parameters);
     }
 
     Input {
-        tests,
+        groups,
         expected_runner,
+        includes,
+    }
+}
+
+/// [`extract_tests`] on the content of `path`, with the tests of included files (resolved
+/// relative to the including file) in place of their directives
+///
+/// # Errors
+/// if a file cannot be read or the includes form a cycle
+pub fn extract_tests_from_file(
+    path: &std::path::Path,
+    lists_to_code_block: bool,
+) -> Result<Input, String> {
+    extract_file(path, lists_to_code_block, &mut Vec::new())
+}
+
+fn extract_file(
+    path: &std::path::Path,
+    lists_to_code_block: bool,
+    including: &mut Vec<std::path::PathBuf>,
+) -> Result<Input, String> {
+    let display = path.display();
+    let canonical = path
+        .canonicalize()
+        .map_err(|err| format!("could not read {display}: {err}"))?;
+    if let Some(start) = including.iter().position(|path| *path == canonical) {
+        let cycle: Vec<_> = including[start..]
+            .iter()
+            .chain(std::iter::once(&canonical))
+            .map(|path| path.display().to_string())
+            .collect();
+        return Err(format!(
+            "include cycle: {cycle}",
+            cycle = cycle.join(" -> ")
+        ));
+    }
+
+    let content =
+        std::fs::read_to_string(path).map_err(|err| format!("could not read {display}: {err}"))?;
+    let mut input = extract_tests(&content, lists_to_code_block);
+
+    including.push(canonical);
+    let directory = path.parent().unwrap_or(std::path::Path::new(""));
+    include_files(&mut input, directory, lists_to_code_block, including)?;
+    including.pop();
+
+    Ok(input)
+}
+
+/// Replaces the include directives in `input` with the tests of the files (relative to
+/// `directory`)
+fn include_files(
+    input: &mut Input,
+    directory: &std::path::Path,
+    lists_to_code_block: bool,
+    including: &mut Vec<std::path::PathBuf>,
+) -> Result<(), String> {
+    // In reverse so that the indices of earlier includes stay the same
+    for (index, include) in std::mem::take(&mut input.includes).into_iter().rev() {
+        let path = directory.join(include);
+        let mut included = extract_file(&path, lists_to_code_block, including)?;
+        for group in &mut included.groups {
+            group.source.get_or_insert_with(|| path.clone());
+        }
+        if input.expected_runner.is_none() {
+            input.expected_runner = included.expected_runner;
+        }
+        input.groups.splice(index..index, included.groups);
     }
+    Ok(())
 }
 
 #[derive(Debug, Default)]
 pub struct TestResults {
     pub count: usize,
     pub skipped: usize,
+    pub ignored: usize,
+    /// failed as expected (see [`Test::is_expected_to_fail`])
+    pub xfailed: usize,
+    /// passed while expected to fail. These are also in `failures`
+    pub xpassed: usize,
+    /// timed by [`bench::run_benchmarks`]
+    pub measured: usize,
     // name, comparison, stderr
     pub failures: Vec<(String, String, String)>,
 }
@@ -202,28 +744,54 @@ impl TestResults {
     pub fn append(&mut self, mut new: TestResults) {
         self.count += new.count;
         self.skipped += new.skipped;
+        self.ignored += new.ignored;
+        self.xfailed += new.xfailed;
+        self.xpassed += new.xpassed;
+        self.measured += new.measured;
         self.failures.append(&mut new.failures);
     }
 }
 
+/// Whether the case is excluded by [`RunConfiguration::filter`] or the tags
+pub(crate) fn is_filtered_out(
+    group: &TestGroup,
+    name: &str,
+    test: &Test,
+    configuration: &RunConfiguration,
+) -> bool {
+    let has_tag = |tags: &[String]| test.tags.iter().any(|tag| tags.contains(tag));
+    configuration
+        .filter
+        .as_ref()
+        .is_some_and(|filter| filter.should_skip_case(&group.name, name))
+        || !(configuration.tags.is_empty() || has_tag(&configuration.tags))
+        || has_tag(&configuration.skip_tags)
+}
+
 pub fn run_tests(
-    tests: &[Test],
+    groups: &[TestGroup],
     runner: &mut impl Runner,
     configuration: &RunConfiguration,
 ) -> TestResults {
     let mut results = TestResults::default();
 
-    for test in tests {
+    let cases = groups.iter().flat_map(|group| {
+        let cases = group.cases.iter().enumerate();
+        cases.map(move |(index, test)| (group, index, test))
+    });
+
+    for (group, index, test) in cases {
         results.count += 1;
-        let name = &test.name;
+        let case_name = group.case_name(index);
+        let name = case_name.as_ref();
 
-        let skip_test = configuration
-            .filter
-            .as_ref()
-            .is_some_and(|filter| filter.should_skip(&test.name));
+        let skip_test = is_filtered_out(group, name, test, configuration);
+        let ignore_test = !skip_test && test.is_ignored();
 
         if skip_test {
             results.skipped += 1;
+        } else if ignore_test {
+            results.ignored += 1;
         }
 
         let name: std::borrow::Cow<'_, str> = if name.contains(['*', '`']) {
@@ -253,8 +821,19 @@ pub fn run_tests(
 
         if configuration.dry_run {
             // TODO should dry run print debug out
-            if !skip_test {
-                let result = runner.run(test);
+            if !(skip_test || ignore_test) {
+                let result = if test.transcript {
+                    transcript::run(test, &configuration.normalisation)
+                        .map(|()| (test.case.clone(), String::new()))
+                } else if test.session {
+                    let outputs: Result<Vec<String>, String> = test
+                        .step_tests()
+                        .map(|step| runner.run(&step).map(|(output, _debug)| output))
+                        .collect();
+                    outputs.map(|outputs| (outputs.join("\n"), String::new()))
+                } else {
+                    runner.run(test)
+                };
                 if configuration.interactive {
                     let should_break = run_in_alternative_display(|| {
                         match result {
@@ -280,39 +859,69 @@ pub fn run_tests(
                 }
             }
         } else if skip_test {
-            if !configuration.skip_print_test_results {
+            if !(configuration.skip_print_test_results || configuration.quiet) {
                 println!("test {name} ... {result}", result = "skipped".blue());
             }
+        } else if ignore_test {
+            if !(configuration.skip_print_test_results || configuration.quiet) {
+                println!("test {name} ... {result}", result = "ignored".yellow());
+            }
         } else {
-            let result = runner.run(test);
-            let result = match result {
-                Ok((output, debug)) => {
-                    if let Some(ref expected) = test.expected {
-                        if is_equal_ignore_new_line_sequence(&output, expected) {
+            let result = if test.transcript {
+                transcript::run(test, &configuration.normalisation)
+                    .map_err(|diffs| (diffs, String::new()))
+            } else if test.session {
+                run_session(test, runner, configuration)
+            } else {
+                match runner.run(test) {
+                    Ok((output, debug)) => {
+                        let diffs = check_output(test, &output, runner.directory(), configuration);
+                        if diffs.is_empty() {
                             Ok(())
                         } else {
-                            let comparison =
-                                pretty_assertions::StrComparison::new(expected, &output)
-                                    .to_string();
-                            Err((comparison, debug))
+                            Err((diffs, debug))
                         }
-                    } else {
-                        Ok(())
                     }
+                    Err(err) => Err((String::default(), err)),
                 }
-                Err(err) => Err((String::default(), err)),
             };
 
-            if !configuration.skip_print_test_results {
+            let print = !(configuration.skip_print_test_results || configuration.quiet);
+            let result = if test.is_expected_to_fail() {
                 if result.is_ok() {
-                    println!("test {name} ... {result}", result = "ok".green()); // "passed"?
+                    results.xpassed += 1;
+                    if print {
+                        println!("test {name} ... {result}", result = "xpassed".red());
+                    }
+                    Err((
+                        "unexpectedly passed (marked xfail)".to_owned(),
+                        String::new(),
+                    ))
                 } else {
-                    println!("test {name} ... {result}", result = "fail".red()); // "failed" ?
+                    results.xfailed += 1;
+                    if print {
+                        println!("test {name} ... {result}", result = "xfailed".yellow());
+                    }
+                    Ok(())
                 }
-            }
+            } else {
+                if print {
+                    if result.is_ok() {
+                        println!("test {name} ... {result}", result = "ok".green()); // "passed"?
+                    } else {
+                        println!("test {name} ... {result}", result = "fail".red()); // "failed" ?
+                    }
+                }
+                result
+            };
 
-            if let Err((output, debug)) = result {
-                results.failures.push((test.name.clone(), output, debug));
+            if let Err((mut output, debug)) = result {
+                if let Some(ref source) = group.source {
+                    output.insert_str(0, &format!("(from {source})\n", source = source.display()));
+                }
+                results
+                    .failures
+                    .push((case_name.clone().into_owned(), output, debug));
             }
         }
     }
@@ -320,6 +929,130 @@ pub fn run_tests(
     results
 }
 
+pub(crate) const SESSION_REQUIRES_STATE: &str =
+    "`> Session` requires a runner which keeps state between steps (such as `--rpc`)";
+
+/// Runs each step of a session, stopping at the first which does not match
+fn run_session(
+    test: &Test,
+    runner: &mut impl Runner,
+    configuration: &RunConfiguration,
+) -> Result<(), (String, String)> {
+    if !runner.keeps_state() {
+        return Err((SESSION_REQUIRES_STATE.to_owned(), String::new()));
+    }
+
+    let total = test.steps.len();
+    for (index, step) in test.step_tests().enumerate() {
+        let number = index + 1;
+        match runner.run(&step) {
+            Ok((output, debug)) => {
+                let diffs = check_output(&step, &output, runner.directory(), configuration);
+                if !diffs.is_empty() {
+                    return Err((format!("step {number} of {total}:\n{diffs}"), debug));
+                }
+            }
+            Err(err) => return Err((format!("step {number} of {total} errored"), err)),
+        }
+    }
+    Ok(())
+}
+
+/// Returns a diff of `output` against [`Test::expected`] followed by any from
+/// [`compare_files`]. Empty if they match
+fn check_output(
+    test: &Test,
+    output: &str,
+    directory: Option<&std::path::Path>,
+    configuration: &RunConfiguration,
+) -> String {
+    let mut diffs = String::new();
+    if let Some(ref expected) = test.expected {
+        let output = configuration.normalisation.apply(output);
+        let expected = configuration.normalisation.apply(expected);
+        if !is_equal_ignore_new_line_sequence(&output, &expected) {
+            let comparison = pretty_assertions::StrComparison::new(&expected, &output);
+            diffs = comparison.to_string();
+        }
+    }
+    if !test.expected_files.is_empty() || configuration.report_extra_files {
+        diffs.push_str(&compare_files(directory, test, configuration));
+    }
+    diffs
+}
+
+/// Returns a diff for each of [`Test::expected_files`] which does not match and (if
+/// [`RunConfiguration::report_extra_files`]) a line for each other file in `directory`
+fn compare_files(
+    directory: Option<&std::path::Path>,
+    test: &Test,
+    configuration: &RunConfiguration,
+) -> String {
+    use std::fmt::Write;
+
+    /// Paths relative to `root`, separated with `/`
+    fn walk(root: &std::path::Path, path: &std::path::Path, out: &mut Vec<String>) {
+        let Ok(entries) = std::fs::read_dir(path) else {
+            return;
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            if path.is_dir() {
+                walk(root, &path, out);
+            } else if let Ok(relative) = path.strip_prefix(root) {
+                let components = relative.components();
+                let parts: Vec<_> = components
+                    .map(|part| part.as_os_str().to_string_lossy())
+                    .collect();
+                out.push(parts.join("/"));
+            }
+        }
+    }
+
+    let Some(directory) = directory else {
+        if test.expected_files.is_empty() {
+            return String::new();
+        }
+        return "runner does not support expected files\n".to_owned();
+    };
+
+    let mut diffs = String::new();
+    for (path, expected) in &test.expected_files {
+        match std::fs::read_to_string(directory.join(path)) {
+            Ok(output) => {
+                let output = configuration.normalisation.apply(&output);
+                let expected = configuration.normalisation.apply(expected);
+                let (output, expected) = (output.trim_end(), expected.trim_end());
+                if !is_equal_ignore_new_line_sequence(output, expected) {
+                    let comparison = pretty_assertions::StrComparison::new(expected, output);
+                    writeln!(diffs, "{path}:\n{comparison}").unwrap();
+                }
+            }
+            Err(err) => writeln!(diffs, "{path}: could not read ({err})").unwrap(),
+        }
+    }
+
+    if configuration.report_extra_files {
+        let mut paths = Vec::new();
+        walk(directory, directory, &mut paths);
+        paths.sort();
+
+        let case_file = runners::program::case_file_name(test);
+        let known = test.files.iter().chain(&test.expected_files);
+        let mut known: Vec<&str> = known
+            .map(|(path, _)| path.trim_start_matches("./"))
+            .collect();
+        known.push(&case_file);
+        for path in paths {
+            if !known.contains(&path.as_str()) {
+                writeln!(diffs, "{path}: unexpected file").unwrap();
+            }
+        }
+    }
+
+    diffs
+}
+
 pub fn run_tests_under_glob(
     pattern: &str,
     mut runner: impl Runner,
@@ -327,6 +1060,22 @@ pub fn run_tests_under_glob(
 ) -> Result<(), usize> {
     let now = std::time::Instant::now();
     let mut results = TestResults::default();
+    let mut measurements = Vec::new();
+
+    let baseline = match configuration
+        .bench
+        .as_ref()
+        .and_then(|bench| bench.baseline.as_ref())
+    {
+        Some(path) => match bench::read_baseline(path) {
+            Ok(baseline) => baseline,
+            Err(err) => {
+                eprintln!("{err}");
+                return Err(1);
+            }
+        },
+        None => bench::Baseline::new(),
+    };
 
     let paths = glob::glob(pattern)
         .unwrap()
@@ -334,14 +1083,45 @@ pub fn run_tests_under_glob(
         .filter(|path| path.is_file());
 
     for path in paths {
-        let content = std::fs::read_to_string(path).unwrap();
-        let input = extract_tests(&content, configuration.lists_to_code_block);
-        let result = run_tests(&input.tests, &mut runner, configuration);
-        results.append(result);
+        match extract_tests_from_file(&path, configuration.lists_to_code_block) {
+            Ok(input) => {
+                if let Some(bench) = &configuration.bench {
+                    let (result, mut measured) = bench::run_benchmarks(
+                        &input.groups,
+                        &mut runner,
+                        configuration,
+                        bench,
+                        &baseline,
+                    );
+                    results.append(result);
+                    measurements.append(&mut measured);
+                } else {
+                    let result = run_tests(&input.groups, &mut runner, configuration);
+                    results.append(result);
+                }
+            }
+            Err(err) => {
+                let name = path.display().to_string();
+                results.count += 1;
+                results.failures.push((name, String::new(), err));
+            }
+        }
     }
 
     runner.close();
 
+    let mut saved = true;
+    if let Some(path) = configuration
+        .bench
+        .as_ref()
+        .and_then(|bench| bench.save.as_ref())
+        && let Err(err) = bench::save(path, &measurements)
+    {
+        // Not a test, so it is not part of the results
+        eprintln!("{err}");
+        saved = false;
+    }
+
     let elapsed = now.elapsed();
     if configuration.dry_run {
         Ok(())
@@ -350,28 +1130,39 @@ pub fn run_tests_under_glob(
         if !configuration.skip_print_test_results {
             print_test_results(results, configuration, elapsed);
         }
-        if failures == 0 { Ok(()) } else { Err(failures) }
+        if failures == 0 && saved {
+            Ok(())
+        } else {
+            Err(failures.max(1))
+        }
     }
 }
 
 /// Runs tests with runner and configuration, printing errors to stdout and stderr.
-/// The output (should) mirror Rust's default test harness
+/// The output (should) mirror Rust's default test harness. Included files are resolved relative
+/// to the current directory
 ///
 /// # Errors
-/// returns the number of failed tests
+/// returns the number of failed tests (or 1 if an included file cannot be read)
 pub fn run_tests_under_content(
     content: &str,
     mut runner: impl Runner,
     configuration: &RunConfiguration,
 ) -> Result<(), usize> {
-    let input = extract_tests(content, configuration.lists_to_code_block);
-    let count = input.tests.len();
+    let lists_to_code_block = configuration.lists_to_code_block;
+    let mut input = extract_tests(content, lists_to_code_block);
+    let current = std::path::Path::new("");
+    if let Err(err) = include_files(&mut input, current, lists_to_code_block, &mut Vec::new()) {
+        eprintln!("{err}");
+        return Err(1);
+    }
+    let count = input.tests().count();
 
     println!("\nrunning {count} tests");
 
     let now = std::time::Instant::now();
 
-    let results = run_tests(&input.tests, &mut runner, configuration);
+    let results = run_tests(&input.groups, &mut runner, configuration);
     let elapsed = now.elapsed();
     if configuration.dry_run {
         Ok(())
@@ -393,6 +1184,10 @@ pub fn print_test_results(
         count,
         failures,
         skipped,
+        ignored,
+        xfailed,
+        xpassed,
+        measured,
     } = results;
 
     if !failures.is_empty() {
@@ -429,15 +1224,12 @@ pub fn print_test_results(
     }
 
     let result = if failures.is_empty() { "ok" } else { "err" };
-    let passed = count - (failures.len() + skipped);
+    let passed = count.saturating_sub(failures.len() + skipped + ignored + xfailed + measured);
     let failed = failures.len();
 
-    // FUTURE will we support these?
-    let ignored = 0;
-    let measured = 0;
     let filtered_out = skipped;
 
     eprintln!(
-        "\ntest result: {result}. {passed} passed; {failed} failed; {ignored} ignored; {measured} measured; {filtered_out} filtered out; finished in {elapsed:?}"
+        "\ntest result: {result}. {passed} passed; {failed} failed; {ignored} ignored; {xfailed} xfailed; {xpassed} xpassed; {measured} measured; {filtered_out} filtered out; finished in {elapsed:?}"
     );
 }
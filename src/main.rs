@@ -1,4 +1,7 @@
-use spectra::{RunConfiguration, extract_tests, run_tests_under_glob, runners, utilities::filter};
+use spectra::{
+    RunConfiguration, bench::BenchConfiguration, configuration::Configuration,
+    extract_tests_from_file, run_tests_under_glob, runners, utilities::filter,
+};
 
 use lahl::{
     CLI, Endpoint, NamedParameter, PositionalParameter, argument_result_or_out,
@@ -7,11 +10,14 @@ use lahl::{
 use std::process::ExitCode;
 
 static TEST_POSITIONAL_PARAMETERS: &[PositionalParameter] = &[
-    PositionalParameter::single("pattern", "glob path to markdown files"),
+    PositionalParameter::single(
+        "pattern",
+        "glob path to markdown files (or name of suite in spectra.toml)",
+    ),
     PositionalParameter::single("command", "command to test against"),
 ];
 
-// TODO timeout & ignore error
+// TODO ignore error
 static TEST_NAMED_PARAMETERS: &[NamedParameter] = &[
     NamedParameter::value("only", "only run tests with *value* in the name"),
     NamedParameter::value(
@@ -23,6 +29,14 @@ static TEST_NAMED_PARAMETERS: &[NamedParameter] = &[
         "skip-cs",
         "skip tests with *value* in the name (case-sensitive)",
     ),
+    NamedParameter::value(
+        "tag",
+        "only run tests with one of these (comma separated) tags",
+    ),
+    NamedParameter::value(
+        "skip-tag",
+        "skip tests with one of these (comma separated) tags",
+    ),
     NamedParameter::boolean(
         "interactive",
         "use stdin <-> stdout communication rather that spawning for each test",
@@ -32,6 +46,25 @@ static TEST_NAMED_PARAMETERS: &[NamedParameter] = &[
         "lists-as-expected",
         "use list blocks as the expected output",
     ),
+    NamedParameter::value("timeout", "timeout for program in milliseconds"),
+    NamedParameter::boolean("quiet", "only print failures and the summary"),
+    NamedParameter::boolean(
+        "report-extra-files",
+        "fail tests which write files that are not expected",
+    ),
+    NamedParameter::boolean(
+        "watch",
+        "rebuild and re-run when the `rust:` library or specification changes",
+    ),
+    NamedParameter::boolean("bench", "time cases rather than check their output"),
+    NamedParameter::value("warmup", "(bench) runs of each case before measuring"),
+    NamedParameter::value("iterations", "(bench) measured runs of each case"),
+    NamedParameter::value("baseline", "(bench) compare against results saved here"),
+    NamedParameter::value("save", "(bench) save results to this path"),
+    NamedParameter::value(
+        "threshold",
+        "(bench) percent slower than the baseline that is a regression",
+    ),
 ];
 
 static LIST_NAMED_PARAMETERS: &[PositionalParameter] = &[PositionalParameter::single(
@@ -49,6 +82,11 @@ static LIST_PARAMETERS: &[NamedParameter] = &[
     NamedParameter::value("cases-with-splitter", "print cases with passed splitter"),
 ];
 
+static RUST_WORKER_POSITIONAL_PARAMETERS: &[PositionalParameter] = &[
+    PositionalParameter::single("artifact", "path to built library"),
+    PositionalParameter::single("function", "name of exported function"),
+];
+
 static ENDPOINTS: &[Endpoint] = &[
     Endpoint::new("info", "display information", &[], &[]),
     Endpoint::new(
@@ -57,6 +95,12 @@ static ENDPOINTS: &[Endpoint] = &[
         TEST_POSITIONAL_PARAMETERS,
         TEST_NAMED_PARAMETERS,
     ),
+    Endpoint::new(
+        "bench",
+        "time tests (same as `test --bench`)",
+        TEST_POSITIONAL_PARAMETERS,
+        TEST_NAMED_PARAMETERS,
+    ),
     Endpoint::new(
         "compare",
         "run two programs against tests",
@@ -64,6 +108,12 @@ static ENDPOINTS: &[Endpoint] = &[
         TEST_NAMED_PARAMETERS,
     ),
     Endpoint::new("list", "list tests", LIST_NAMED_PARAMETERS, LIST_PARAMETERS),
+    Endpoint::new(
+        "rust-worker",
+        "(internal) serve calls to a library for `rust:* --isolate`",
+        RUST_WORKER_POSITIONAL_PARAMETERS,
+        &[],
+    ),
     Endpoint::new_group(
         "install",
         "specification-test-in-cargo",
@@ -97,10 +147,13 @@ fn run() -> Result<(), ExitCode> {
 
             println!("spectra{version} {after} (powered by 'simple-markdown-parser')");
         }
-        "test" | "compare" => {
+        "test" | "bench" | "compare" => {
             let mut pattern = None;
             let mut command = None;
             let mut run_configuration = RunConfiguration::default();
+            let mut timeout = None;
+            let mut watch = false;
+            let mut bench = (selected.name == "bench").then(BenchConfiguration::default);
 
             for argument in arguments {
                 let argument = argument_result_or_out(argument)?;
@@ -121,18 +174,64 @@ fn run() -> Result<(), ExitCode> {
                         };
                         run_configuration.filter = Some(Box::new(filter));
                     }
+                    name @ ("tag" | "skip-tag") => {
+                        let value = argument.value.unwrap();
+                        let tags = value.split(',').map(|tag| tag.trim().to_owned());
+                        if name == "tag" {
+                            run_configuration.tags.extend(tags);
+                        } else {
+                            run_configuration.skip_tags.extend(tags);
+                        }
+                    }
                     // run configuration
                     "interactive" => run_configuration.interactive = true,
                     "dry-run" => run_configuration.dry_run = true,
                     "lists-as-expected" => run_configuration.lists_to_code_block = true,
-                    // // command configuration
+                    "quiet" => run_configuration.quiet = true,
+                    "report-extra-files" => run_configuration.report_extra_files = true,
+                    "watch" => watch = true,
+                    // bench configuration
+                    "bench" => {
+                        bench.get_or_insert_with(BenchConfiguration::default);
+                    }
+                    name @ ("warmup" | "iterations") => {
+                        let value = argument.value.unwrap();
+                        let value: usize = value.parse().expect("expected count");
+                        let bench = bench.get_or_insert_with(BenchConfiguration::default);
+                        if name == "warmup" {
+                            bench.warmup = value;
+                        } else {
+                            bench.iterations = value;
+                        }
+                    }
+                    name @ ("baseline" | "save") => {
+                        let path = argument.value.unwrap().into();
+                        let bench = bench.get_or_insert_with(BenchConfiguration::default);
+                        if name == "baseline" {
+                            bench.baseline = Some(path);
+                        } else {
+                            bench.save = Some(path);
+                        }
+                    }
+                    "threshold" => {
+                        let value = argument.value.unwrap();
+                        let percent: f64 = value.parse().expect("expected percent");
+                        let bench = bench.get_or_insert_with(BenchConfiguration::default);
+                        bench.threshold = percent / 100.;
+                    }
+                    // command configuration
+                    "timeout" => {
+                        let value = argument.value.unwrap();
+                        let milliseconds: u64 = value.parse().expect("expected millisecond");
+                        timeout = Some(std::time::Duration::from_millis(milliseconds));
+                    }
                     // "ignore-exit-code" => command_configuration.ignore_exit_code = true,
-                    // "stdin-stdout-communication" => command_configuration.stdin_stdout_communication = true,
                     argument => unreachable!("{argument:?}"),
                 }
             }
 
             let pattern = pattern.unwrap();
+            run_configuration.bench = bench;
 
             if selected.name == "compare" {
                 let command_pattern = command.unwrap();
@@ -144,13 +243,133 @@ fn run() -> Result<(), ExitCode> {
                     return Err(ExitCode::FAILURE);
                 }
             } else {
-                let command = command.unwrap();
+                let mut command_configuration = runners::program::CommandConfiguration::default();
+
+                // A single positional argument refers to a suite. Values on the command line
+                // take precedence over the suite
+                let (pattern, command) = if let Some(command) = command {
+                    (pattern, command)
+                } else {
+                    let configuration = match Configuration::find() {
+                        Ok(Some(configuration)) => configuration,
+                        Ok(None) => {
+                            eprintln!("no command passed and no spectra.toml found");
+                            return Err(ExitCode::FAILURE);
+                        }
+                        Err(err) => {
+                            eprintln!("{err}");
+                            return Err(ExitCode::FAILURE);
+                        }
+                    };
+                    let Some(suite) = configuration.get(&pattern) else {
+                        eprintln!("no suite named '{pattern}'");
+                        return Err(ExitCode::FAILURE);
+                    };
+
+                    if run_configuration.filter.is_none() {
+                        run_configuration.filter = suite
+                            .filter()
+                            .map(|filter| Box::new(filter) as Box<dyn filter::Filter>);
+                    }
+                    run_configuration.interactive |= suite.interactive;
+                    run_configuration.lists_to_code_block |= suite.lists_as_expected;
+                    run_configuration.quiet |= suite.quiet;
+                    run_configuration.report_extra_files |= suite.report_extra_files;
+                    if run_configuration.tags.is_empty() {
+                        run_configuration.tags.clone_from(&suite.tags);
+                    }
+                    if run_configuration.skip_tags.is_empty() {
+                        run_configuration.skip_tags.clone_from(&suite.skip_tags);
+                    }
+                    run_configuration.normalisation = suite.normalisation.clone();
+                    command_configuration = suite.command.clone();
+
+                    (suite.pattern.clone(), suite.runner.clone())
+                };
+
+                if let Some(timeout) = timeout {
+                    command_configuration.timeout = Some(timeout);
+                }
+
+                if watch && !command.starts_with("rust:") {
+                    eprintln!("--watch is only supported by the `rust:` runner");
+                    return Err(ExitCode::FAILURE);
+                }
+
                 let result = if let Some(after) = command.strip_prefix("rust:") {
-                    let (path, name) = after.split_once("::").unwrap_or((after, "test"));
-                    let runner = runners::compiled::rust::Rust::new(path, name).unwrap();
+                    let configuration = runners::compiled::rust::RustConfiguration {
+                        timeout: command_configuration.timeout,
+                        ..Default::default()
+                    };
+                    let runner = match runners::compiled::rust::Rust::new_from_argument(
+                        after,
+                        configuration,
+                    ) {
+                        Ok(runner) => runner,
+                        Err(err) => {
+                            eprintln!("{err}");
+                            return Err(ExitCode::FAILURE);
+                        }
+                    };
+                    if watch {
+                        runner.watch(&pattern, &run_configuration);
+                    }
+                    run_tests_under_glob(&pattern, runner, &run_configuration)
+                } else if let Some(after) = command.strip_prefix("wasm:") {
+                    #[cfg(feature = "wasm")]
+                    {
+                        let configuration = runners::compiled::wasm::WasmConfiguration {
+                            timeout: command_configuration.timeout,
+                            ..Default::default()
+                        };
+                        let runner = match runners::compiled::wasm::Wasm::new_from_argument(
+                            after,
+                            configuration,
+                        ) {
+                            Ok(runner) => runner,
+                            Err(err) => {
+                                eprintln!("{err}");
+                                return Err(ExitCode::FAILURE);
+                            }
+                        };
+                        run_tests_under_glob(&pattern, runner, &run_configuration)
+                    }
+                    #[cfg(not(feature = "wasm"))]
+                    {
+                        let _ = after;
+                        eprintln!("spectra was built without the `wasm` feature");
+                        return Err(ExitCode::FAILURE);
+                    }
+                } else if let Some(after) = command.strip_prefix("script:") {
+                    let runner = match runners::script::Script::new_from_argument(
+                        after,
+                        command_configuration,
+                    ) {
+                        Ok(runner) => runner,
+                        Err(err) => {
+                            eprintln!("{err}");
+                            return Err(ExitCode::FAILURE);
+                        }
+                    };
+                    run_tests_under_glob(&pattern, runner, &run_configuration)
+                } else if let Some(after) = command.strip_prefix("c:") {
+                    let configuration = runners::compiled::c::CConfiguration::default();
+                    let runner = match runners::compiled::c::C::new_from_argument(
+                        after,
+                        configuration,
+                    ) {
+                        Ok(runner) => runner,
+                        Err(err) => {
+                            eprintln!("{err}");
+                            return Err(ExitCode::FAILURE);
+                        }
+                    };
                     run_tests_under_glob(&pattern, runner, &run_configuration)
                 } else {
-                    let command = runners::program::Command::new(&command);
+                    let command = runners::program::Command::new_with_configuration(
+                        &command,
+                        command_configuration,
+                    );
                     run_tests_under_glob(&pattern, command, &run_configuration)
                 };
                 if result.is_err() {
@@ -202,35 +421,48 @@ fn run() -> Result<(), ExitCode> {
                 .filter(|path| path.is_file());
 
             for path in paths {
-                let content = std::fs::read_to_string(&path).unwrap();
-                let input = extract_tests(&content, lists_to_code_block);
+                let input = match extract_tests_from_file(&path, lists_to_code_block) {
+                    Ok(input) => input,
+                    Err(err) => {
+                        eprintln!("{err}");
+                        return Err(ExitCode::FAILURE);
+                    }
+                };
                 if as_json {
-                    for test in &input.tests {
-                        if json_buf.len() > 1 {
-                            json_buf.push(',');
+                    for group in &input.groups {
+                        for (index, test) in group.cases.iter().enumerate() {
+                            if json_buf.len() > 1 {
+                                json_buf.push(',');
+                            }
+                            let name = group.case_name(index);
+                            // FUTURE json_builder_macro should support `Option`
+                            let expected = test.expected.as_deref().unwrap_or_default();
+                            json_buf.push_str(&json_builder_macro::json! {
+                                name: name, case: test.case, expected: expected
+                            });
                         }
-                        // FUTURE json_builder_macro should support `Option`
-                        let expected = test.expected.as_deref().unwrap_or_default();
-                        json_buf.push_str(&json_builder_macro::json! {
-                            name: test.name, case: test.case, expected: expected
-                        });
                     }
                 } else {
                     if case_splitter.is_none() {
                         println!("--- {path} ---", path = path.display());
                     }
-                    for test in &input.tests {
-                        if debug {
-                            println!("{test:?}");
-                        } else if let Some(splitter) = &case_splitter {
-                            if count > 0 {
-                                println!("{splitter}");
+                    for group in &input.groups {
+                        for (index, test) in group.cases.iter().enumerate() {
+                            if debug {
+                                println!("{test:?}");
+                            } else if let Some(splitter) = &case_splitter {
+                                if count > 0 {
+                                    println!("{splitter}");
+                                }
+                                println!("{case}", case = test.case);
+                            } else if let Some(ref source) = group.source {
+                                let name = group.case_name(index);
+                                println!("{name} (from {source})", source = source.display());
+                            } else {
+                                println!("{name}", name = group.case_name(index));
                             }
-                            println!("{case}", case = test.case);
-                        } else {
-                            println!("{name}", name = test.name);
+                            count += 1;
                         }
-                        count += 1;
                     }
                 }
                 files += 1;
@@ -243,6 +475,31 @@ fn run() -> Result<(), ExitCode> {
                 eprintln!("found {count} tests across {files} files");
             }
         }
+        "rust-worker" => {
+            let mut artifact = None;
+            let mut function = None;
+
+            for argument in arguments {
+                let argument = argument_result_or_out(argument)?;
+                match argument.name {
+                    "artifact" => {
+                        artifact = argument.value;
+                    }
+                    "function" => {
+                        function = argument.value;
+                    }
+                    argument => unreachable!("{argument}"),
+                }
+            }
+
+            let artifact = artifact.unwrap();
+            let function = function.unwrap();
+
+            if let Err(err) = runners::compiled::rust::serve_worker(&artifact, &function) {
+                eprintln!("{err}");
+                return Err(ExitCode::FAILURE);
+            }
+        }
         "specification-test-in-cargo" => {
             use std::io::Write;
 
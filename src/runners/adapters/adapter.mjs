@@ -0,0 +1,60 @@
+// Embedded by `spectra` for the `script:` runner. Usage: adapter.mjs *module path* *function name*
+//
+// Responds with `ok *n*` (or `err *n*`), then *n* lines of result, then anything logged during the
+// call, then `end`
+import { createInterface } from "node:readline";
+import { resolve } from "node:path";
+import { pathToFileURL } from "node:url";
+import { format } from "node:util";
+
+// Printing in the module goes to stderr
+const error = console.error;
+console.log = error;
+
+const [path, name] = process.argv.slice(2);
+const module = await import(pathToFileURL(resolve(path)).href);
+const func = module[name] ?? module.default?.[name];
+if (typeof func !== "function") {
+	throw Error(`${path} does not export function ${name}`);
+}
+
+const write = (content) => new Promise((res) => process.stdout.write(content, res));
+
+await write("start\n");
+let buffer = "";
+for await (const line of createInterface({ input: process.stdin })) {
+	if (line === "close") break;
+
+	if (line === "end") {
+		let captured = "";
+		const capture = (...items) => {
+			captured += format(...items);
+			captured += "\n";
+		};
+		console.log = console.error = console.warn = console.info = capture;
+
+		let tag = "ok";
+		let result;
+		try {
+			// Without the new line added by the framing
+			result = await func(buffer.endsWith("\n") ? buffer.slice(0, -1) : buffer);
+		} catch (err) {
+			capture(err);
+			tag = "err";
+			result = String(err);
+		}
+		console.log = console.error = console.warn = console.info = error;
+
+		const lines = result === undefined || result === null ? [] : String(result).split(/\r?\n/);
+		if (lines.at(-1) === "") lines.pop();
+		const rest = captured.split("\n").slice(0, -1);
+		await write([`${tag} ${lines.length}`, ...lines, ...rest, "end", ""].join("\n"));
+		buffer = "";
+		continue;
+	}
+
+	buffer += line;
+	buffer += "\n";
+}
+
+process.exit(0);
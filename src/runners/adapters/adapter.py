@@ -0,0 +1,56 @@
+# Embedded by `spectra` for the `script:` runner. Usage: adapter.py *module path* *function name*
+#
+# Responds with `ok *n*` (or `err *n*`), then *n* lines of result, then anything printed during the
+# call, then `end`
+import contextlib
+import importlib.util
+import io
+import os
+import sys
+import traceback
+
+
+def main():
+    path, name = sys.argv[1], sys.argv[2]
+
+    # Printing in the module goes to stderr
+    protocol = sys.stdout
+    sys.stdout = sys.stderr
+
+    sys.path.insert(0, os.path.dirname(os.path.abspath(path)))
+    spec = importlib.util.spec_from_file_location("spectra_module", path)
+    module = importlib.util.module_from_spec(spec)
+    spec.loader.exec_module(module)
+    function = getattr(module, name)
+
+    protocol.write("start\n")
+    protocol.flush()
+
+    buffer = ""
+    for line in sys.stdin:
+        line = line.rstrip("\n")
+        if line == "close":
+            break
+        if line == "end":
+            captured = io.StringIO()
+            with contextlib.redirect_stdout(captured), contextlib.redirect_stderr(captured):
+                try:
+                    # Without the new line added by the framing
+                    result = function(buffer.removesuffix("\n"))
+                    tag = "ok"
+                except Exception as error:
+                    traceback.print_exc()
+                    result = f"{type(error).__name__}: {error}"
+                    tag = "err"
+            lines = ("" if result is None else str(result)).splitlines()
+            protocol.write(f"{tag} {len(lines)}\n")
+            for line in lines + captured.getvalue().splitlines():
+                protocol.write(f"{line}\n")
+            protocol.write("end\n")
+            protocol.flush()
+            buffer = ""
+            continue
+        buffer += line + "\n"
+
+
+main()
@@ -0,0 +1,165 @@
+//! Runner for shared libraries (written in C, C++, Zig etc) which export
+//!
+//! ```c
+//! // Returns 0 if `*output` is the result, otherwise `*output` is an error message.
+//! // `input` is UTF-8 and not null terminated
+//! int32_t test(const uint8_t *input, size_t input_length, uint8_t **output, size_t *output_length);
+//! // Called with the output once it has been copied
+//! void test_free(uint8_t *output, size_t output_length);
+//! ```
+//!
+//! See `examples/c-runner`
+
+use crate::{Runner, Test};
+
+type FunctionType = unsafe extern "C" fn(
+    input: *const u8,
+    input_length: usize,
+    output: *mut *mut u8,
+    output_length: *mut usize,
+) -> i32;
+
+type FreeType = unsafe extern "C" fn(output: *mut u8, output_length: usize);
+
+#[derive(Debug, Default, Clone)]
+pub struct CConfiguration {
+    /// make calls directly in this process rather than in a fork of it (on unix), so that state
+    /// in the library (such as statics) persists between calls. A crash in the library ends the
+    /// run
+    pub in_process: bool,
+}
+
+pub struct C {
+    /// we need to hold this so that the functions are valid
+    _library: libloading::Library,
+    function: libloading::Symbol<'static, FunctionType>,
+    /// `*function*_free`
+    free: libloading::Symbol<'static, FreeType>,
+    configuration: CConfiguration,
+}
+
+impl C {
+    /// # Errors
+    /// if the library cannot be loaded or does not have the exports
+    pub fn new(path: &str, name: &str) -> Result<Self, String> {
+        Self::new_with_configuration(path, name, CConfiguration::default())
+    }
+
+    /// From `*path*::*function*` followed by `--in-process`
+    ///
+    /// # Errors
+    /// if the library cannot be loaded or does not have the exports
+    pub fn new_from_argument(
+        argument: &str,
+        mut configuration: CConfiguration,
+    ) -> Result<Self, String> {
+        let mut iter = crate::utilities::ArgumentIter::new(argument);
+        let Some(specification) = iter.next() else {
+            return Err("no path passed".to_owned());
+        };
+        let (path, name) = specification
+            .split_once("::")
+            .unwrap_or((&specification, "test"));
+
+        for flag in iter {
+            match flag.as_ref() {
+                "--in-process" => configuration.in_process = true,
+                flag => return Err(format!("unknown flag {flag:?}")),
+            }
+        }
+
+        Self::new_with_configuration(path, name, configuration)
+    }
+
+    /// # Errors
+    /// if the library cannot be loaded or does not have the exports
+    #[allow(clippy::missing_transmute_annotations, clippy::used_underscore_binding)]
+    pub fn new_with_configuration(
+        path: &str,
+        name: &str,
+        configuration: CConfiguration,
+    ) -> Result<Self, String> {
+        unsafe {
+            let Ok(_library) = libloading::Library::new(path) else {
+                return Err(format!("library {path:?} does not exist"));
+            };
+            let Ok(function): Result<libloading::Symbol<'_, FunctionType>, _> =
+                _library.get(name.as_bytes())
+            else {
+                return Err(format!("library {path:?} does not have export {name}"));
+            };
+            let free_name = format!("{name}_free");
+            let Ok(free): Result<libloading::Symbol<'_, FreeType>, _> =
+                _library.get(free_name.as_bytes())
+            else {
+                return Err(format!("library {path:?} does not have export {free_name}"));
+            };
+
+            // Promote to higher lifetime as library is owned
+            let function = std::mem::transmute(function);
+            let free = std::mem::transmute(free);
+            Ok(Self {
+                _library,
+                function,
+                free,
+                configuration,
+            })
+        }
+    }
+
+    fn call(&self, case: &str) -> Result<String, String> {
+        let mut output: *mut u8 = std::ptr::null_mut();
+        let mut length: usize = 0;
+
+        // SAFETY: the library is trusted to follow the documented signature
+        let status =
+            unsafe { (self.function)(case.as_ptr(), case.len(), &raw mut output, &raw mut length) };
+
+        let content = if output.is_null() {
+            String::new()
+        } else {
+            // SAFETY: `output` points to `length` bytes until it is freed
+            unsafe {
+                let bytes = std::slice::from_raw_parts(output, length);
+                let content = String::from_utf8_lossy(bytes).into_owned();
+                (self.free)(output, length);
+                content
+            }
+        };
+
+        if status == 0 {
+            Ok(content)
+        } else if content.is_empty() {
+            Err(format!("returned status {status}"))
+        } else {
+            Err(content)
+        }
+    }
+}
+
+impl Runner for C {
+    fn run(&mut self, test: &Test) -> Result<(String, String), String> {
+        #[cfg(unix)]
+        {
+            let (out, captured) = if self.configuration.in_process {
+                super::call_capturing_output(|| self.call(&test.case))?
+            } else {
+                super::call_in_fork(|| self.call(&test.case))?
+            };
+            match out {
+                Ok(out) => Ok((out, captured)),
+                Err(out) if captured.is_empty() => Err(out),
+                Err(out) => Err(format!("{out}\n{captured}")),
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            self.call(&test.case).map(|out| (out, String::new()))
+        }
+    }
+
+    /// Not if calls are made in a fork
+    fn keeps_state(&self) -> bool {
+        self.configuration.in_process || cfg!(not(unix))
+    }
+}
@@ -1 +1,170 @@
+pub mod c;
 pub mod rust;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// Makes the call in this process with `stdout` and `stderr` redirected to a file for the
+/// duration of the call. State in the library (such as statics) persists between calls. Only the
+/// `std` of this process is flushed, so the call should flush any output buffered by the library
+///
+/// Returns the result of the call and anything printed during it
+#[cfg(unix)]
+pub(crate) fn call_capturing_output<T>(call: impl FnOnce() -> T) -> Result<(T, String), String> {
+    use std::io::{Read, Seek, Write};
+    use std::os::fd::AsRawFd;
+
+    let mut output = crate::utilities::temporary_file().map_err(|err| err.to_string())?;
+
+    // So that earlier output is not captured
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stderr().flush();
+
+    // SAFETY: the original descriptors are restored (and the copies closed) after the call
+    let out = unsafe {
+        let stdout = libc::dup(libc::STDOUT_FILENO);
+        let stderr = libc::dup(libc::STDERR_FILENO);
+        if stdout == -1 || stderr == -1 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        libc::dup2(output.as_raw_fd(), libc::STDOUT_FILENO);
+        libc::dup2(output.as_raw_fd(), libc::STDERR_FILENO);
+
+        let out = call();
+
+        let _ = std::io::stdout().flush();
+        libc::dup2(stdout, libc::STDOUT_FILENO);
+        libc::dup2(stderr, libc::STDERR_FILENO);
+        libc::close(stdout);
+        libc::close(stderr);
+        out
+    };
+
+    let mut captured = String::new();
+    let _ = output.rewind();
+    let _ = output.read_to_string(&mut captured);
+    captured.truncate(captured.trim_end().len());
+    Ok((out, captured))
+}
+
+/// Crashes in the library (such as a panic or a segfault) would take down this process. Instead
+/// the call is made in a fork of this process (which already has the library loaded), with
+/// `stdout` and `stderr` redirected to a file for the duration of the call. As the fork exits
+/// after the call, state in the library does not persist between calls
+///
+/// Returns the result of the call and anything printed during it
+#[cfg(unix)]
+pub(crate) fn call_in_fork(
+    call: impl FnOnce() -> Result<String, String>,
+) -> Result<(Result<String, String>, String), String> {
+    use std::io::{Read, Seek, Write};
+    use std::os::fd::AsRawFd;
+
+    let mut output = crate::utilities::temporary_file().map_err(|err| err.to_string())?;
+    let mut result = crate::utilities::temporary_file().map_err(|err| err.to_string())?;
+
+    // So that buffered output is not written twice
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stderr().flush();
+
+    // SAFETY: the child only calls the function, writes the result and exits
+    let pid = unsafe { libc::fork() };
+    if pid == -1 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+
+    if pid == 0 {
+        // SAFETY: file descriptors are valid. `_exit` skips destructors belonging to the parent
+        unsafe {
+            libc::dup2(output.as_raw_fd(), libc::STDOUT_FILENO);
+            libc::dup2(output.as_raw_fd(), libc::STDERR_FILENO);
+
+            let out = call();
+            let (tag, content) = match &out {
+                Ok(out) => (b'o', out),
+                Err(out) => (b'e', out),
+            };
+            let _ = result.write_all(&[tag]);
+            let _ = result.write_all(content.as_bytes());
+            let _ = std::io::stdout().flush();
+            libc::_exit(0);
+        }
+    }
+
+    let mut status: libc::c_int = 0;
+    // SAFETY: `pid` is a child of this process
+    unsafe {
+        libc::waitpid(pid, &raw mut status, 0);
+    }
+
+    let mut captured = String::new();
+    let _ = output.rewind();
+    let _ = output.read_to_string(&mut captured);
+    // From the unwind reaching this copy of `std`
+    let captured = captured
+        .lines()
+        .filter(|line| {
+            !line.starts_with("fatal runtime error: Rust cannot catch foreign exceptions")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut buf = Vec::new();
+    let _ = result.rewind();
+    let _ = result.read_to_end(&mut buf);
+
+    match buf.split_first() {
+        Some((tag, content)) if libc::WIFEXITED(status) => {
+            let content = String::from_utf8_lossy(content).into_owned();
+            let out = if *tag == b'o' {
+                Ok(content)
+            } else {
+                Err(content)
+            };
+            Ok((out, captured))
+        }
+        _ => {
+            if let Some(message) = panic_message(&captured) {
+                Err(format!("PANICKED: {message}\n{captured}"))
+            } else if libc::WIFSIGNALED(status) {
+                let signal = libc::WTERMSIG(status);
+                Err(format!("CRASHED (signal {signal})\n{captured}"))
+            } else {
+                Err(format!("CRASHED\n{captured}"))
+            }
+        }
+    }
+}
+
+/// From the default panic hook, which prints the location and then the message
+pub(crate) fn panic_message(output: &str) -> Option<String> {
+    let mut lines = output.lines();
+    lines.find(|line| line.contains(" panicked at "))?;
+    let message: Vec<&str> = lines
+        .take_while(|line| {
+            !(line.starts_with("note: run with `RUST_BACKTRACE") || *line == "stack backtrace:")
+        })
+        .collect();
+    Some(message.join("\n"))
+}
+
+#[cfg(test)]
+#[test]
+fn panic_message_from_hook() {
+    let output = "before\n\nthread 'main' panicked at lib.rs:4:28:\noh no\nnote: run with `RUST_BACKTRACE=1` environment variable to display a backtrace";
+    assert_eq!(panic_message(output).as_deref(), Some("oh no"));
+    assert_eq!(panic_message("nothing here"), None);
+}
+
+#[cfg(all(test, unix))]
+#[test]
+fn capturing_output() {
+    use std::io::Write;
+
+    // `print!` is captured by the test harness, so this writes to the descriptor directly
+    let (out, captured) = call_capturing_output(|| {
+        let _ = std::io::stdout().write_all(b"from the library\n");
+        2
+    })
+    .unwrap();
+    assert_eq!((out, captured.as_str()), (2, "from the library"));
+}
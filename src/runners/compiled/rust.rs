@@ -1,93 +1,912 @@
-use crate::{Runner, Test};
+use crate::runners::program;
+use crate::{RunConfiguration, Runner, Test};
 
-// FUTURE this may have problems with FFI
+use std::time;
+
+/// The original signature, used when the library does not export [`abi::VERSION_SYMBOL`]. This
+/// relies on the unstable `extern "Rust"` layout, so the library must be built with the same
+/// compiler as spectra
 type FunctionType = unsafe extern "Rust" fn(&str) -> Result<String, String>;
 
+/// FFI-safe signature which has access to the test metadata. Libraries opt in with
+///
+/// ```ignore
+/// #[unsafe(no_mangle)]
+/// pub static SPECTRA_ABI_VERSION: u32 = 1;
+///
+/// #[unsafe(no_mangle)]
+/// pub unsafe extern "C" fn test(input: *const Input, output: *mut Output) -> i32 {
+///     ...
+/// }
+/// ```
+///
+/// where the types are `#[repr(C)]` copies of the ones here (see `examples/rust-runner-abi`)
+pub mod abi {
+    use std::ffi::c_void;
+
+    pub const VERSION_SYMBOL: &str = "SPECTRA_ABI_VERSION";
+    pub const VERSION: u32 = 1;
+
+    /// Optional `extern "C" fn()` (with either signature) called after each call. The library has
+    /// its own copy of `std`, so output it has buffered (such as `print!` without a newline) can
+    /// only be flushed from the library
+    pub const FLUSH_SYMBOL: &str = "spectra_flush";
+
+    /// Borrowed UTF-8 string
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct Str {
+        pub pointer: *const u8,
+        pub length: usize,
+    }
+
+    impl Str {
+        #[must_use]
+        pub fn new(on: &str) -> Self {
+            Self {
+                pointer: on.as_ptr(),
+                length: on.len(),
+            }
+        }
+
+        /// # Safety
+        /// `pointer` and `length` must describe a valid UTF-8 string for the returned lifetime
+        #[must_use]
+        pub unsafe fn as_str<'a>(self) -> &'a str {
+            unsafe {
+                let bytes = std::slice::from_raw_parts(self.pointer, self.length);
+                std::str::from_utf8_unchecked(bytes)
+            }
+        }
+    }
+
+    #[repr(C)]
+    pub struct Input {
+        pub case: Str,
+        pub options: Str,
+        pub name: Str,
+        pub section: Str,
+        /// of the code block the case was taken from
+        pub language: Str,
+    }
+
+    /// The library calls `write` (any number of times) to build its output. The content is copied
+    /// so the library keeps ownership of its strings
+    #[repr(C)]
+    pub struct Output {
+        pub context: *mut c_void,
+        pub write: unsafe extern "C" fn(context: *mut c_void, content: Str),
+    }
+
+    /// Returns `0` if the output is the result, otherwise the output is an error message
+    pub type Function = unsafe extern "C" fn(input: *const Input, output: *mut Output) -> i32;
+
+    /// The library is not trusted to write UTF-8, so invalid sequences are replaced
+    pub(super) unsafe extern "C" fn write_to_string(target: *mut c_void, content: Str) {
+        unsafe {
+            let buf = &mut *target.cast::<String>();
+            let bytes = std::slice::from_raw_parts(content.pointer, content.length);
+            buf.push_str(&String::from_utf8_lossy(bytes));
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn abi_write_invalid_utf8() {
+    let mut buf = String::new();
+    let content = abi::Str {
+        pointer: b"a\xffb".as_ptr(),
+        length: 3,
+    };
+    unsafe { abi::write_to_string((&raw mut buf).cast(), content) };
+    assert_eq!(buf, "a\u{fffd}b");
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RustConfiguration {
+    /// load the library in a worker process (`spectra rust-worker`) rather than in this process,
+    /// so that crashes and infinite loops do not affect the run
+    pub isolate: bool,
+    /// make calls directly in this process rather than in a fork of it (on unix), so that state
+    /// in the library (such as statics) persists between calls. A crash in the library (such as a
+    /// panic) ends the run
+    pub in_process: bool,
+    /// only applies when `isolate`d
+    pub timeout: Option<time::Duration>,
+    /// spectra binary which serves `rust-worker` for `isolate`. The current executable if not set,
+    /// which is not spectra when it is used as a library
+    pub executable: Option<std::path::PathBuf>,
+    pub build: BuildOptions,
+}
+
+/// Options passed to `cargo rustc`
+#[derive(Debug, Default, Clone)]
+pub struct BuildOptions {
+    /// `dev` if not set
+    pub profile: Option<String>,
+    pub features: Vec<String>,
+    /// for libraries in a workspace
+    pub package: Option<String>,
+    /// passed to `rustc` (after `--`)
+    pub rustc_flags: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Build(BuildError),
+    Other(String),
+}
+
+impl From<String> for Error {
+    fn from(value: String) -> Self {
+        Self::Other(value)
+    }
+}
+
+impl From<&'_ str> for Error {
+    fn from(value: &'_ str) -> Self {
+        Self::Other(value.to_owned())
+    }
+}
+
+impl From<BuildError> for Error {
+    fn from(value: BuildError) -> Self {
+        Self::Build(value)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Build(error) => std::fmt::Display::fmt(error, f),
+            Self::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BuildError {
+    /// exit code of `cargo`. `None` if it could not be run or was terminated
+    pub status: Option<i32>,
+    /// warnings and errors from the compiler
+    pub diagnostics: Vec<Diagnostic>,
+    /// if the build succeeded but did not produce a library
+    pub message: Option<String>,
+}
+
+impl BuildError {
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.level.starts_with("error"))
+    }
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(message) = &self.message {
+            write!(f, "could not build library: {message}")?;
+        } else if let Some(status) = self.status {
+            write!(f, "could not build library (exit code {status})")?;
+        } else {
+            write!(f, "could not build library")?;
+        }
+        for diagnostic in self.errors() {
+            writeln!(f)?;
+            match &diagnostic.rendered {
+                Some(rendered) => write!(f, "{}", rendered.trim_end())?,
+                None => write!(f, "{}: {}", diagnostic.level, diagnostic.message)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// From a `compiler-message`
+#[derive(Debug)]
+pub struct Diagnostic {
+    /// `error`, `warning` etc
+    pub level: String,
+    pub message: String,
+    /// as printed by `rustc`
+    pub rendered: Option<String>,
+}
+
 pub struct Rust {
+    inner: Inner,
+    /// of the crate, for rebuilding
+    path: String,
+    name: String,
+    configuration: RustConfiguration,
+    /// copy of the artifact loaded by [`Rust::reload`]
+    copy: Option<std::path::PathBuf>,
+}
+
+enum Inner {
+    InProcess(Library),
+    Worker(Box<program::Command>),
+}
+
+#[allow(clippy::struct_field_names)]
+struct Library {
     /// we need to hold this so that the function is valid
     _library: libloading::Library,
     /// function callback
-    function: libloading::Symbol<'static, FunctionType>,
+    function: Function,
+    /// [`abi::FLUSH_SYMBOL`]
+    flush: Option<libloading::Symbol<'static, unsafe extern "C" fn()>>,
+}
+
+enum Function {
+    Legacy(libloading::Symbol<'static, FunctionType>),
+    Abi(libloading::Symbol<'static, abi::Function>),
+}
+
+/// Messages printed by `cargo --message-format json`
+#[derive(Debug, Default)]
+struct BuildOutput {
+    artifact: Option<String>,
+    diagnostics: Vec<Diagnostic>,
 }
 
-// TODO after `compiler-artifact`?
-fn get_output_name_from_json(json_output: &str) -> Option<&str> {
-    let prefix = "\"filenames\":[\"";
-    let start: usize = json_output.rfind(prefix)? + prefix.len();
-    let after: &str = &json_output[start..];
-    let end = after.find('\"')?;
-    Some(&after[..end])
+fn parse_build_output(json_output: &str) -> BuildOutput {
+    use std::collections::HashMap;
+    use tinyjson::JsonValue;
+
+    fn string(on: &JsonValue, key: &str) -> Option<String> {
+        on.get::<HashMap<String, JsonValue>>()?
+            .get(key)?
+            .get::<String>()
+            .cloned()
+    }
+
+    let mut output = BuildOutput::default();
+
+    for line in json_output.lines() {
+        let Ok(message) = line.parse::<JsonValue>() else {
+            continue;
+        };
+        let Some(object) = message.get::<HashMap<String, JsonValue>>() else {
+            continue;
+        };
+
+        match string(&message, "reason").as_deref() {
+            Some("compiler-artifact") => {
+                let is_library = object
+                    .get("target")
+                    .and_then(|target| target.get::<HashMap<String, JsonValue>>())
+                    .and_then(|target| target.get("crate_types"))
+                    .and_then(|crate_types| crate_types.get::<Vec<JsonValue>>())
+                    .is_some_and(|crate_types| {
+                        crate_types
+                            .iter()
+                            .any(|item| item.get::<String>().is_some_and(|item| item == "cdylib"))
+                    });
+
+                if !is_library {
+                    continue;
+                }
+
+                let filenames = object
+                    .get("filenames")
+                    .and_then(|filenames| filenames.get::<Vec<JsonValue>>());
+
+                if let Some(filenames) = filenames {
+                    let artifact = filenames
+                        .iter()
+                        .filter_map(|filename| filename.get::<String>())
+                        .find(|filename| filename.ends_with(std::env::consts::DLL_SUFFIX));
+
+                    if let Some(artifact) = artifact {
+                        output.artifact = Some(artifact.clone());
+                    }
+                }
+            }
+            Some("compiler-message") => {
+                let Some(inner) = object.get("message") else {
+                    continue;
+                };
+                output.diagnostics.push(Diagnostic {
+                    level: string(inner, "level").unwrap_or_default(),
+                    message: string(inner, "message").unwrap_or_default(),
+                    rendered: string(inner, "rendered"),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    output
 }
 
 #[cfg(test)]
 #[test]
 fn test() {
-    let out = get_output_name_from_json(
-        r#"{"reason":"compiler-artifact","package_id":"path+file:///Users/benjamin/Projects/spectra/examples/rust-runner#0.1.0","manifest_path":"/Users/benjamin/Projects/spectra/examples/rust-runner/Cargo.toml","target":{"kind":["cdylib"],"crate_types":["cdylib"],"name":"rust_runner","src_path":"/Users/benjamin/Projects/spectra/examples/rust-runner/lib.rs","edition":"2024","doc":true,"doctest":false,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/Users/benjamin/Projects/spectra/examples/rust-runner/target/debug/librust_runner.dylib"],"executable":null,"fresh":true}"#,
+    let dependency = r#"{"reason":"compiler-artifact","package_id":"registry+https://github.com/rust-lang/crates.io-index#dependency@0.1.0","manifest_path":"/home/dependency/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"dependency","src_path":"/home/dependency/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/target/debug/deps/libdependency.rlib"],"executable":null,"fresh":true}"#;
+    let warning = r#"{"reason":"compiler-message","package_id":"path+file:///examples/rust-runner#0.1.0","manifest_path":"/examples/rust-runner/Cargo.toml","target":{"kind":["cdylib"],"crate_types":["cdylib"],"name":"rust_runner","src_path":"/examples/rust-runner/lib.rs","edition":"2024","doc":true,"doctest":false,"test":true},"message":{"rendered":"warning: unused variable: `x`\n","$message_type":"diagnostic","children":[],"code":null,"level":"warning","message":"unused variable: `x`","spans":[]}}"#;
+    let library = format!(
+        r#"{{"reason":"compiler-artifact","package_id":"path+file:///Users/benjamin/Projects/spectra/examples/rust-runner#0.1.0","manifest_path":"/Users/benjamin/Projects/spectra/examples/rust-runner/Cargo.toml","target":{{"kind":["cdylib"],"crate_types":["cdylib"],"name":"rust_runner","src_path":"/Users/benjamin/Projects/spectra/examples/rust-runner/lib.rs","edition":"2024","doc":true,"doctest":false,"test":true}},"profile":{{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false}},"features":[],"filenames":["/Users/benjamin/Projects/spectra/examples/rust-runner/target/debug/librust_runner{suffix}"],"executable":null,"fresh":true}}"#,
+        suffix = std::env::consts::DLL_SUFFIX
     );
+    let finished = r#"{"reason":"build-finished","success":true}"#;
+
+    let out = parse_build_output(&[dependency, warning, &library, finished].join("\n"));
     assert_eq!(
-        out,
-        Some(
-            "/Users/benjamin/Projects/spectra/examples/rust-runner/target/debug/librust_runner.dylib"
-        )
+        out.artifact,
+        Some(format!(
+            "/Users/benjamin/Projects/spectra/examples/rust-runner/target/debug/librust_runner{suffix}",
+            suffix = std::env::consts::DLL_SUFFIX
+        ))
     );
+    assert_eq!(out.diagnostics.len(), 1);
+    assert_eq!(out.diagnostics[0].level, "warning");
+    assert_eq!(out.diagnostics[0].message, "unused variable: `x`");
 }
 
 impl Rust {
-    #[allow(clippy::missing_transmute_annotations, clippy::used_underscore_binding)]
-    pub fn new(path: &str, name: &str) -> Result<Self, String> {
-        let output = std::process::Command::new("cargo")
-            .arg("rustc")
-            .arg("--crate-type")
-            .arg("cdylib")
-            .arg("--message-format")
-            .arg("json")
-            .stderr(std::process::Stdio::inherit())
-            .current_dir(path)
-            .output();
-
-        let Ok(output) = output else {
-            return Err("could not build library".to_owned());
+    /// # Errors
+    /// if the library cannot be built or loaded
+    pub fn new(path: &str, name: &str) -> Result<Self, Error> {
+        Self::new_with_configuration(path, name, &RustConfiguration::default())
+    }
+
+    /// From `*path*::*function*` followed by flags (such as `--isolate` or `--release`) which are
+    /// applied on top of `configuration`. Arguments after `--` are passed to `rustc`
+    ///
+    /// # Errors
+    /// if the library cannot be built or loaded
+    pub fn new_from_argument(
+        argument: &str,
+        mut configuration: RustConfiguration,
+    ) -> Result<Self, Error> {
+        let mut iter = crate::utilities::ArgumentIter::new(argument);
+        let Some(specification) = iter.next() else {
+            return Err("no path passed".into());
         };
-        if !output.status.success() {
-            return Err("could not build library".to_owned());
+        let (path, name) = specification
+            .split_once("::")
+            .unwrap_or((&specification, "test"));
+
+        while let Some(flag) = iter.next() {
+            match flag.as_ref() {
+                "--isolate" => configuration.isolate = true,
+                "--in-process" => configuration.in_process = true,
+                "--timeout" => {
+                    let value = iter.next().ok_or("expected millisecond")?;
+                    let time: u64 = value.parse().map_err(|_| "expected millisecond")?;
+                    configuration.timeout = Some(time::Duration::from_millis(time));
+                }
+                "--release" => configuration.build.profile = Some("release".to_owned()),
+                "--profile" => {
+                    let value = iter.next().ok_or("expected profile")?;
+                    configuration.build.profile = Some(value.into_owned());
+                }
+                "--features" | "-F" => {
+                    let value = iter.next().ok_or("expected features")?;
+                    let features = value.split([',', ' ']).filter(|item| !item.is_empty());
+                    configuration
+                        .build
+                        .features
+                        .extend(features.map(str::to_owned));
+                }
+                "--package" | "-p" => {
+                    let value = iter.next().ok_or("expected package")?;
+                    configuration.build.package = Some(value.into_owned());
+                }
+                "--" => {
+                    let rest = iter.by_ref().map(std::borrow::Cow::into_owned);
+                    configuration.build.rustc_flags.extend(rest);
+                }
+                flag => return Err(format!("unknown flag {flag:?}").into()),
+            }
         }
 
-        let out_json = str::from_utf8(&output.stdout).unwrap();
-        let Some(artifact_name) = get_output_name_from_json(out_json) else {
-            return Err(format!("JSON does not contain artifact: {out_json}"));
+        Self::new_with_configuration(path, name, &configuration)
+    }
+
+    /// # Errors
+    /// if the library cannot be built or loaded
+    pub fn new_with_configuration(
+        path: &str,
+        name: &str,
+        configuration: &RustConfiguration,
+    ) -> Result<Self, Error> {
+        let artifact_name = build(path, &configuration.build)?;
+        let inner = Inner::new(&artifact_name, name, configuration)?;
+
+        Ok(Self {
+            inner,
+            path: path.to_owned(),
+            name: name.to_owned(),
+            configuration: configuration.clone(),
+            copy: None,
+        })
+    }
+
+    /// Rebuilds the library and swaps it in. The artifact is copied to a unique filename first as
+    /// the loader caches libraries by path
+    ///
+    /// # Errors
+    /// if the library cannot be built or loaded. The previous library is kept
+    pub fn reload(&mut self) -> Result<(), Error> {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+        let artifact_name = build(&self.path, &self.configuration.build)?;
+
+        let count = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let file_name = std::path::Path::new(&artifact_name)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let copy = std::env::temp_dir().join(format!(
+            "spectra-{pid}-{count}-{file_name}",
+            pid = std::process::id()
+        ));
+        std::fs::copy(&artifact_name, &copy)
+            .map_err(|err| format!("could not copy {artifact_name:?}: {err}"))?;
+
+        let inner = match Inner::new(&copy.display().to_string(), &self.name, &self.configuration) {
+            Ok(inner) => inner,
+            Err(err) => {
+                let _ = std::fs::remove_file(&copy);
+                return Err(err);
+            }
         };
 
+        let old = std::mem::replace(&mut self.inner, inner);
+        old.close();
+        if let Some(previous) = self.copy.replace(copy) {
+            let _ = std::fs::remove_file(previous);
+        }
+        Ok(())
+    }
+
+    /// Runs the tests under `pattern`, then waits for changes to the crate (or the markdown
+    /// files), reloads the library and runs them again. Does not return
+    pub fn watch(mut self, pattern: &str, configuration: &RunConfiguration) -> ! {
+        let mut last = self.modified_times(pattern);
+        loop {
+            let _ = crate::run_tests_under_glob(pattern, &mut self, configuration);
+            eprintln!("watching {path} for changes", path = self.path);
+            self.wait_for_change(pattern, &mut last);
+
+            eprintln!("rebuilding");
+            while let Err(err) = self.reload() {
+                eprintln!("{err}");
+                eprintln!("waiting for changes");
+                self.wait_for_change(pattern, &mut last);
+            }
+        }
+    }
+
+    fn wait_for_change(
+        &self,
+        pattern: &str,
+        last: &mut Vec<(std::path::PathBuf, time::SystemTime)>,
+    ) {
+        const POLL: time::Duration = time::Duration::from_millis(300);
+
+        loop {
+            std::thread::sleep(POLL);
+            let current = self.modified_times(pattern);
+            if current != *last {
+                *last = current;
+                return;
+            }
+        }
+    }
+
+    /// Of source files in the crate and the markdown files under `pattern`
+    fn modified_times(&self, pattern: &str) -> Vec<(std::path::PathBuf, time::SystemTime)> {
+        fn walk(path: &std::path::Path, out: &mut Vec<(std::path::PathBuf, time::SystemTime)>) {
+            let Ok(entries) = std::fs::read_dir(path) else {
+                return;
+            };
+            for entry in entries.filter_map(Result::ok) {
+                let path = entry.path();
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                if path.is_dir() {
+                    if !(name == "target" || name.starts_with('.')) {
+                        walk(&path, out);
+                    }
+                } else if (name.ends_with(".rs") || name == "Cargo.toml")
+                    && let Ok(modified) = entry.metadata().and_then(|metadata| metadata.modified())
+                {
+                    out.push((path, modified));
+                }
+            }
+        }
+
+        let mut times = Vec::new();
+        walk(std::path::Path::new(&self.path), &mut times);
+
+        if let Ok(paths) = glob::glob(pattern) {
+            for path in paths.filter_map(Result::ok) {
+                if let Ok(modified) = path.metadata().and_then(|metadata| metadata.modified()) {
+                    times.push((path, modified));
+                }
+            }
+        }
+
+        times.sort();
+        times
+    }
+}
+
+impl Inner {
+    fn new(
+        artifact_name: &str,
+        name: &str,
+        configuration: &RustConfiguration,
+    ) -> Result<Self, Error> {
+        if configuration.isolate {
+            let executable = match &configuration.executable {
+                Some(executable) => executable.clone(),
+                None => std::env::current_exe()
+                    .map_err(|err| format!("could not find spectra executable for worker: {err}"))?,
+            };
+            let arguments = vec![
+                "rust-worker".to_owned(),
+                artifact_name.to_owned(),
+                name.to_owned(),
+            ];
+            let configuration = program::CommandConfiguration {
+                stdin_stdout_communication: true,
+                timeout: configuration.timeout,
+                ..program::CommandConfiguration::default()
+            };
+            let executable = executable.display().to_string();
+            let command =
+                program::Command::try_from_parts(executable.clone(), arguments, configuration)
+                    .map_err(|err| {
+                        format!("could not start `{executable} rust-worker` for --isolate: {err}")
+                    })?;
+            Ok(Self::Worker(Box::new(command)))
+        } else {
+            Ok(Self::InProcess(Library::load(artifact_name, name)?))
+        }
+    }
+
+    fn close(self) {
+        if let Self::Worker(command) = self {
+            command.close();
+        }
+    }
+}
+
+/// Returns the path to the built library
+fn build(path: &str, options: &BuildOptions) -> Result<String, BuildError> {
+    let mut command = std::process::Command::new("cargo");
+    command
+        .arg("rustc")
+        .arg("--lib")
+        .arg("--crate-type")
+        .arg("cdylib")
+        .arg("--message-format")
+        .arg("json");
+
+    if let Some(profile) = &options.profile {
+        command.arg("--profile").arg(profile);
+    }
+    if !options.features.is_empty() {
+        command.arg("--features").arg(options.features.join(","));
+    }
+    if let Some(package) = &options.package {
+        command.arg("--package").arg(package);
+    }
+    if !options.rustc_flags.is_empty() {
+        command.arg("--").args(&options.rustc_flags);
+    }
+
+    let output = command
+        .stderr(std::process::Stdio::inherit())
+        .current_dir(path)
+        .output();
+
+    let output = match output {
+        Ok(output) => output,
+        Err(err) => {
+            return Err(BuildError {
+                status: None,
+                diagnostics: Vec::new(),
+                message: Some(format!("could not run cargo: {err}")),
+            });
+        }
+    };
+
+    let BuildOutput {
+        artifact,
+        diagnostics,
+    } = parse_build_output(&String::from_utf8_lossy(&output.stdout));
+
+    let message = if !output.status.success() {
+        None
+    } else if let Some(artifact) = artifact {
+        return Ok(artifact);
+    } else {
+        Some("no `cdylib` artifact produced".to_owned())
+    };
+
+    Err(BuildError {
+        status: output.status.code(),
+        diagnostics,
+        message,
+    })
+}
+
+impl Library {
+    #[allow(clippy::missing_transmute_annotations, clippy::used_underscore_binding)]
+    fn load(artifact_name: &str, name: &str) -> Result<Self, String> {
         unsafe {
             let Ok(_library) = libloading::Library::new(artifact_name) else {
                 return Err(format!("library {artifact_name:?} does not exist"));
             };
-            let Ok(function): Result<libloading::Symbol<'_, FunctionType>, _> =
-                _library.get(name.as_bytes())
-            else {
-                return Err(format!(
-                    "library {artifact_name:?} does not have export {name}"
-                ));
-            };
+
+            let version: Option<u32> = _library
+                .get::<*const u32>(abi::VERSION_SYMBOL.as_bytes())
+                .ok()
+                .map(|version| **version);
 
             // Promote to higher lifetime as library is owned
-            let function = std::mem::transmute(function);
-            Ok(Self { _library, function })
+            let function = match version {
+                None => {
+                    let Ok(function): Result<libloading::Symbol<'_, FunctionType>, _> =
+                        _library.get(name.as_bytes())
+                    else {
+                        return Err(format!(
+                            "library {artifact_name:?} does not have export {name}"
+                        ));
+                    };
+                    Function::Legacy(std::mem::transmute(function))
+                }
+                Some(abi::VERSION) => {
+                    let Ok(function): Result<libloading::Symbol<'_, abi::Function>, _> =
+                        _library.get(name.as_bytes())
+                    else {
+                        return Err(format!(
+                            "library {artifact_name:?} does not have export {name}"
+                        ));
+                    };
+                    Function::Abi(std::mem::transmute(function))
+                }
+                Some(version) => {
+                    return Err(format!(
+                        "library {artifact_name:?} uses unsupported ABI version {version} (expected {expected})",
+                        expected = abi::VERSION
+                    ));
+                }
+            };
+
+            let flush = _library
+                .get::<unsafe extern "C" fn()>(abi::FLUSH_SYMBOL.as_bytes())
+                .ok()
+                .map(|flush| std::mem::transmute(flush));
+
+            Ok(Self {
+                _library,
+                function,
+                flush,
+            })
+        }
+    }
+
+    fn call(&self, test: &Test) -> Result<String, String> {
+        let out = self.call_function(test);
+        if let Some(flush) = &self.flush {
+            unsafe { flush() };
+        }
+        out
+    }
+
+    fn call_function(&self, test: &Test) -> Result<String, String> {
+        match &self.function {
+            Function::Legacy(function) => unsafe { function(&test.case) },
+            Function::Abi(function) => {
+                let input = abi::Input {
+                    case: abi::Str::new(&test.case),
+                    options: abi::Str::new(&test.options),
+                    name: abi::Str::new(&test.name),
+                    section: abi::Str::new(&test.section),
+                    language: abi::Str::new(&test.language),
+                };
+                let mut buf = String::new();
+                let mut output = abi::Output {
+                    context: (&raw mut buf).cast(),
+                    write: abi::write_to_string,
+                };
+                let status = unsafe { function(&raw const input, &raw mut output) };
+                if status == 0 { Ok(buf) } else { Err(buf) }
+            }
+        }
+    }
+}
+
+/// Entry point for `spectra rust-worker *artifact* *function*`. Serves calls using the same
+/// protocol as `--rpc` programs. Responses are prefixed with a `ok` or `err` line. Output from the
+/// library is sent to `stderr`
+///
+/// # Errors
+/// if the library cannot be loaded
+pub fn serve_worker(artifact_name: &str, name: &str) -> Result<(), String> {
+    use std::io::{BufRead, Write};
+
+    let library = Library::load(artifact_name, name)?;
+
+    let mut protocol = protocol_output();
+    let _ = writeln!(protocol, "start");
+    let _ = protocol.flush();
+
+    let mut buf = String::new();
+    for line in std::io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        match line.as_str() {
+            "close" => break,
+            "end" => {
+                let out = library.call(&decode_for_worker(&buf));
+                let (tag, content) = match &out {
+                    Ok(content) => ("ok", content),
+                    Err(content) => ("err", content),
+                };
+                let _ = writeln!(protocol, "{tag}");
+                for line in content.lines() {
+                    let _ = writeln!(protocol, "{line}");
+                }
+                let _ = writeln!(protocol, "end");
+                let _ = protocol.flush();
+                buf.clear();
+            }
+            line => {
+                buf.push_str(line);
+                buf.push('\n');
+            }
         }
     }
+
+    Ok(())
+}
+
+/// The worker protocol only sends a case, so the metadata is sent as a header of name, section,
+/// language and the number of lines of options (followed by the options)
+fn encode_for_worker(test: &Test) -> Test {
+    let Test {
+        section,
+        name,
+        options,
+        case,
+        language,
+        timeout,
+        ..
+    } = test;
+    let lines = options.lines().count();
+    let mut encoded = format!("{name}\n{section}\n{language}\n{lines}\n");
+    for line in options.lines() {
+        encoded.push_str(line);
+        encoded.push('\n');
+    }
+    encoded.push_str(case);
+    Test {
+        case: encoded,
+        timeout: *timeout,
+        ..Test::default()
+    }
+}
+
+fn decode_for_worker(on: &str) -> Test {
+    let mut lines = on.split_inclusive('\n');
+    let mut next = || lines.next().unwrap_or_default().trim_end_matches('\n');
+    let name = next().to_owned();
+    let section = next().to_owned();
+    let language = next().to_owned();
+    let count: usize = next().parse().unwrap_or_default();
+    let options = (0..count).map(|_| next()).collect::<Vec<_>>().join("\n");
+    let case = lines.collect();
+    Test {
+        section,
+        name,
+        options,
+        case,
+        language,
+        ..Test::default()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn worker_encoding() {
+    let test = Test {
+        section: "Section".into(),
+        name: "Name".into(),
+        options: "a\nb".into(),
+        case: "case\n".into(),
+        language: "rs".into(),
+        ..Test::default()
+    };
+    let decoded = decode_for_worker(&encode_for_worker(&test).case);
+    assert_eq!(decoded.section, test.section);
+    assert_eq!(decoded.name, test.name);
+    assert_eq!(decoded.options, test.options);
+    assert_eq!(decoded.case, test.case);
+    assert_eq!(decoded.language, test.language);
+}
+
+/// Moves `stdout` to `stderr` so that printing in the library does not interfere
+#[cfg(unix)]
+fn protocol_output() -> Box<dyn std::io::Write> {
+    use std::os::fd::FromRawFd;
+
+    // SAFETY: the duplicated descriptor is owned by the returned file
+    unsafe {
+        let protocol = libc::dup(libc::STDOUT_FILENO);
+        if protocol == -1 {
+            return Box::new(std::io::stdout());
+        }
+        libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO);
+        Box::new(std::fs::File::from_raw_fd(protocol))
+    }
+}
+
+#[cfg(not(unix))]
+fn protocol_output() -> Box<dyn std::io::Write> {
+    Box::new(std::io::stdout())
 }
 
 impl Runner for Rust {
     fn run(&mut self, test: &Test) -> Result<(String, String), String> {
-        let out = unsafe { (self.function)(&test.case) };
-        match out {
-            Ok(out) => {
+        match &mut self.inner {
+            #[cfg(unix)]
+            Inner::InProcess(library) => {
+                // The library has its own copy of `std`, so a panic from it cannot be caught with
+                // `catch_unwind` here ("Rust cannot catch foreign exceptions"). Instead calls are
+                // made in a fork unless `--in-process`
+                let (out, captured) = if self.configuration.in_process {
+                    super::call_capturing_output(|| library.call(test))?
+                } else {
+                    super::call_in_fork(|| library.call(test))?
+                };
+                match out {
+                    Ok(out) => Ok((out, captured)),
+                    Err(out) if captured.is_empty() => Err(out),
+                    Err(out) => Err(format!("{out}\n{captured}")),
+                }
+            }
+            #[cfg(not(unix))]
+            Inner::InProcess(library) => {
                 // FUTURE collect stderr with technique
-                Ok((out, String::new()))
+                library.call(test).map(|out| (out, String::new()))
+            }
+            Inner::Worker(command) => {
+                let (stdout, stderr) =
+                    command.run(&encode_for_worker(test)).map_err(|message| {
+                        // Output of a crashed worker is prefixed with `* `
+                        let output = message.replace("\n* ", "\n");
+                        let output = output.strip_prefix("* ").unwrap_or(&output);
+                        match super::panic_message(output) {
+                            Some(panic) => format!("PANICKED: {panic}"),
+                            None => message,
+                        }
+                    })?;
+                let (tag, content) = stdout.split_once('\n').unwrap_or((&stdout, ""));
+                match tag {
+                    "ok" => Ok((content.to_owned(), stderr)),
+                    "err" if stderr.is_empty() => Err(content.to_owned()),
+                    "err" => Err(format!("{content}\n{stderr}")),
+                    _ => Err(format!("unexpected response from worker {stdout:?}")),
+                }
             }
-            Err(out) => Err(out),
         }
     }
 
-    fn close(self) {}
+    /// Not if calls are made in a fork
+    fn keeps_state(&self) -> bool {
+        matches!(self.inner, Inner::Worker(_)) || self.configuration.in_process || cfg!(not(unix))
+    }
+
+    fn close(self) {
+        self.inner.close();
+        if let Some(copy) = self.copy {
+            let _ = std::fs::remove_file(copy);
+        }
+    }
 }
@@ -0,0 +1,205 @@
+//! Runner for WebAssembly modules, using an embedded interpreter (`wasmi`). Each test runs in a
+//! new instance of the module, which exports
+//!
+//! ```wat
+//! (memory (export "memory") ...)
+//! ;; returns a pointer to `length` bytes for the input
+//! (func (export "spectra_alloc") (param $length i32) (result i32))
+//! ;; writes a pointer and length (two little-endian `i32`s) of the output to `output`. Returns 0 if
+//! ;; the output is the result, otherwise the output is an error message
+//! (func (export "test") (param $input i32) (param $input_length i32) (param $output i32) (result i32))
+//! ```
+//!
+//! The same signature as the `c:` runner, so one implementation can target both. See
+//! `examples/wasm-runner`
+//!
+//! FUTURE WASI modules
+
+use crate::{Runner, Test};
+
+use std::time::Duration;
+
+/// Rough amount of instructions executed per millisecond. Used to turn timeouts into fuel
+pub const FUEL_PER_MILLISECOND: u64 = 250_000;
+
+#[derive(Debug, Default, Clone)]
+pub struct WasmConfiguration {
+    /// converted to fuel
+    pub timeout: Option<Duration>,
+    /// overrides `timeout`
+    pub fuel: Option<u64>,
+    /// of linear memory, in bytes
+    pub memory: Option<usize>,
+}
+
+pub struct Wasm {
+    engine: wasmi::Engine,
+    module: wasmi::Module,
+    name: String,
+    fuel: Option<u64>,
+    memory: Option<usize>,
+}
+
+struct State {
+    limits: wasmi::StoreLimits,
+}
+
+impl Wasm {
+    /// From `*path*::*function*` followed by `--fuel *n*` or `--memory *size*` flags which are
+    /// applied on top of `configuration`
+    ///
+    /// # Errors
+    /// if the module cannot be read or is not valid
+    pub fn new_from_argument(
+        argument: &str,
+        mut configuration: WasmConfiguration,
+    ) -> Result<Self, String> {
+        let mut iter = crate::utilities::ArgumentIter::new(argument);
+        let Some(specification) = iter.next() else {
+            return Err("no path passed".to_owned());
+        };
+        let (path, name) = specification
+            .split_once("::")
+            .unwrap_or((&specification, "test"));
+
+        while let Some(flag) = iter.next() {
+            match flag.as_ref() {
+                "--fuel" => {
+                    let value = iter.next().ok_or("expected fuel")?;
+                    configuration.fuel = Some(value.parse().map_err(|_| "expected number")?);
+                }
+                "--memory" => {
+                    let value = iter.next().ok_or("expected size")?;
+                    let size = crate::utilities::parse_size(&value).ok_or("expected size")?;
+                    configuration.memory =
+                        Some(usize::try_from(size).map_err(|_| "size is too large")?);
+                }
+                flag => return Err(format!("unknown flag {flag:?}")),
+            }
+        }
+
+        Self::new(path, name, &configuration)
+    }
+
+    /// # Errors
+    /// if the module cannot be read or is not valid
+    pub fn new(path: &str, name: &str, configuration: &WasmConfiguration) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|err| format!("could not read {path:?}: {err}"))?;
+
+        let fuel = configuration.fuel.or_else(|| {
+            configuration.timeout.map(|timeout| {
+                let milliseconds = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+                milliseconds.saturating_mul(FUEL_PER_MILLISECOND)
+            })
+        });
+
+        let mut config = wasmi::Config::default();
+        config.consume_fuel(fuel.is_some());
+        let engine = wasmi::Engine::new(&config);
+        let module = wasmi::Module::new(&engine, &bytes)
+            .map_err(|err| format!("could not load {path:?}: {err}"))?;
+
+        Ok(Self {
+            engine,
+            module,
+            name: name.to_owned(),
+            fuel,
+            memory: configuration.memory,
+        })
+    }
+
+    fn call(&self, case: &str) -> Result<String, String> {
+        let mut limits = wasmi::StoreLimitsBuilder::new();
+        if let Some(memory) = self.memory {
+            limits = limits.memory_size(memory);
+        }
+        let mut store = wasmi::Store::new(
+            &self.engine,
+            State {
+                limits: limits.build(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        if let Some(fuel) = self.fuel {
+            store.set_fuel(fuel).map_err(|err| err.to_string())?;
+        }
+
+        let linker = wasmi::Linker::<State>::new(&self.engine);
+        let instance = linker
+            .instantiate(&mut store, &self.module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(|err| trap_message(&err))?;
+
+        let Some(memory) = instance.get_memory(&store, "memory") else {
+            return Err("module does not export memory".to_owned());
+        };
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&store, "spectra_alloc")
+            .map_err(|err| format!("module does not have export spectra_alloc: {err}"))?;
+        let function = instance
+            .get_typed_func::<(i32, i32, i32), i32>(&store, &self.name)
+            .map_err(|err| format!("module does not have export {}: {err}", self.name))?;
+
+        let input_length = i32::try_from(case.len()).map_err(|_| "case is too large")?;
+        let input = alloc
+            .call(&mut store, input_length)
+            .map_err(|err| trap_message(&err))?;
+        let output = alloc
+            .call(&mut store, 8)
+            .map_err(|err| trap_message(&err))?;
+
+        let out_of_bounds = |_| "pointer out of bounds".to_owned();
+        memory
+            .write(&mut store, address(input)?, case.as_bytes())
+            .map_err(out_of_bounds)?;
+
+        let status = function
+            .call(&mut store, (input, input_length, output))
+            .map_err(|err| trap_message(&err))?;
+
+        let mut pointer_and_length = [0u8; 8];
+        memory
+            .read(&store, address(output)?, &mut pointer_and_length)
+            .map_err(out_of_bounds)?;
+        let (pointer, length) = pointer_and_length.split_at(4);
+        let pointer = u32::from_le_bytes(pointer.try_into().unwrap()) as usize;
+        let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+
+        let mut content = vec![0u8; length];
+        memory
+            .read(&store, pointer, &mut content)
+            .map_err(out_of_bounds)?;
+        let content = String::from_utf8_lossy(&content).into_owned();
+
+        if status == 0 {
+            Ok(content)
+        } else if content.is_empty() {
+            Err(format!("returned status {status}"))
+        } else {
+            Err(content)
+        }
+    }
+}
+
+fn address(pointer: i32) -> Result<usize, String> {
+    usize::try_from(pointer).map_err(|_| format!("invalid pointer {pointer}"))
+}
+
+fn trap_message(error: &wasmi::Error) -> String {
+    if let Some(wasmi::core::TrapCode::OutOfFuel) = error.as_trap_code() {
+        "PROCESS TIMED OUT (fuel exhausted)".to_owned()
+    } else {
+        format!("TRAPPED: {error}")
+    }
+}
+
+impl Runner for Wasm {
+    fn run(&mut self, test: &Test) -> Result<(String, String), String> {
+        self.call(&test.case).map(|out| (out, String::new()))
+    }
+
+    /// Each test is a new instance
+    fn keeps_state(&self) -> bool {
+        false
+    }
+}
@@ -1,2 +1,3 @@
 pub mod compiled;
 pub mod program;
+pub mod script;
@@ -4,15 +4,30 @@ use crate::{Runner, Test};
 use std::io::Write;
 use std::{process, time};
 
-// #[derive(Debug, Default)]
-// pub struct CommandConfiguration {
-//     pub stdin_stdout_communication: bool,
-//     pub ignore_exit_code: bool,
-// }
+#[derive(Debug, Default, Clone)]
+pub struct CommandConfiguration {
+    pub stdin_stdout_communication: bool,
+    pub ignore_exit_code: bool,
+    pub timeout: Option<time::Duration>,
+    /// for the `start` message in `--rpc` mode
+    pub startup_timeout: Option<time::Duration>,
+    pub limits: commands::ResourceLimits,
+    /// run under a pseudo-terminal (unix only) rather than pipes
+    pub pty: Option<commands::PtySize>,
+    /// by default escape sequences are removed from output under a pseudo-terminal
+    pub keep_ansi: bool,
+    /// can contain the `{dir}` placeholder
+    pub working_directory: Option<String>,
+}
+
+/// Used if [`CommandConfiguration::startup_timeout`] is not set
+pub const DEFAULT_STARTUP_TIMEOUT: time::Duration = time::Duration::from_secs(10);
 
 pub struct Running {
-    stdin: process::ChildStdin,
+    stdin: Box<dyn Write + Send>,
     process: commands::Process,
+    /// the last [`Test::setup`] sent to the process
+    setup: String,
 }
 
 pub struct Command {
@@ -20,6 +35,13 @@ pub struct Command {
     arguments: Vec<String>,
     _ignore_exit_code: bool,
     timeout: Option<time::Duration>,
+    startup_timeout: time::Duration,
+    limits: commands::ResourceLimits,
+    pty: Option<commands::PtySize>,
+    keep_ansi: bool,
+    working_directory: Option<String>,
+    /// of the last test, kept for reading the files it wrote
+    directory: Option<crate::utilities::TemporaryDirectory>,
     currently_running: Option<Running>,
 }
 
@@ -29,20 +51,43 @@ impl Command {
     /// panics if `data` is empty
     #[must_use]
     pub fn new(argument: &str) -> Self {
+        Self::new_with_configuration(argument, CommandConfiguration::default())
+    }
+
+    /// Flags in `argument` (such as `--rpc`) are applied on top of `configuration`
+    ///
+    /// # Panics
+    ///
+    /// panics if `data` is empty
+    #[must_use]
+    pub fn new_with_configuration(argument: &str, mut configuration: CommandConfiguration) -> Self {
+        fn take_value(arguments: &mut Vec<String>, flag: &str) -> Option<String> {
+            let idx = arguments.iter().position(|arg| arg == flag)?;
+            // .expect("no value")
+            let value = arguments.remove(idx + 1);
+            arguments.remove(idx);
+            Some(value)
+        }
+
+        fn milliseconds(value: &str) -> time::Duration {
+            let time: u64 = value.parse().expect("expected millisecond");
+            time::Duration::from_millis(time)
+        }
+
+        fn size(value: &str) -> u64 {
+            crate::utilities::parse_size(value).expect("expected size")
+        }
+
         let mut iter = crate::utilities::ArgumentIter::new(argument);
         let name = iter.next().expect("no command name");
         let mut arguments: Vec<String> = iter.map(std::borrow::Cow::into_owned).collect();
 
-        let mut stdin_stdout_communication = false;
-        let mut _ignore_exit_code = false;
-        let mut timeout = None;
-
         if let Some(idx) = arguments
             .iter()
             .position(|arg| matches!(arg.as_str(), "--stdin-stdout-communication" | "--rpc"))
         {
             arguments.remove(idx);
-            stdin_stdout_communication = true;
+            configuration.stdin_stdout_communication = true;
         }
 
         if let Some(idx) = arguments
@@ -50,84 +95,262 @@ impl Command {
             .position(|arg| matches!(arg.as_str(), "--ignore-exit-code"))
         {
             arguments.remove(idx);
-            _ignore_exit_code = true;
+            configuration.ignore_exit_code = true;
         }
 
-        if let Some(idx) = arguments
-            .iter()
-            .position(|arg| matches!(arg.as_str(), "--timeout"))
-        {
-            // .expect("no timeout")
-            let time: u64 = arguments
-                .remove(idx + 1)
-                .parse()
-                .expect("expected millisecond");
+        if let Some(value) = take_value(&mut arguments, "--timeout") {
+            configuration.timeout = Some(milliseconds(&value));
+        }
+        if let Some(value) = take_value(&mut arguments, "--startup-timeout") {
+            configuration.startup_timeout = Some(milliseconds(&value));
+        }
+
+        let limits = &mut configuration.limits;
+        if let Some(value) = take_value(&mut arguments, "--limit-memory") {
+            limits.address_space = Some(size(&value));
+        }
+        if let Some(value) = take_value(&mut arguments, "--limit-cpu") {
+            limits.cpu_seconds = Some(value.parse().expect("expected seconds"));
+        }
+        if let Some(value) = take_value(&mut arguments, "--limit-files") {
+            limits.open_files = Some(value.parse().expect("expected number"));
+        }
+        if let Some(value) = take_value(&mut arguments, "--limit-processes") {
+            limits.processes = Some(value.parse().expect("expected number"));
+        }
+        if let Some(value) = take_value(&mut arguments, "--limit-output") {
+            let value = usize::try_from(size(&value)).expect("output limit too large");
+            limits.output = Some(value);
+        }
+
+        if let Some(idx) = arguments.iter().position(|arg| arg == "--pty") {
             arguments.remove(idx);
-            timeout = Some(time::Duration::from_millis(time));
+            configuration.pty.get_or_insert_default();
+        }
+        if let Some(value) = take_value(&mut arguments, "--pty-size") {
+            configuration.pty = Some(value.parse().expect("expected *columns*x*rows*"));
+        }
+        if let Some(idx) = arguments.iter().position(|arg| arg == "--keep-ansi") {
+            arguments.remove(idx);
+            configuration.keep_ansi = true;
+        }
+        if let Some(value) = take_value(&mut arguments, "--cwd") {
+            configuration.working_directory = Some(value);
         }
 
-        let name = name.into_owned();
+        Self::from_parts(name.into_owned(), arguments, configuration)
+    }
+
+    /// Without parsing flags from the arguments
+    ///
+    /// # Panics
+    ///
+    /// panics if the program does not start in `--rpc` mode
+    #[must_use]
+    pub fn from_parts(
+        name: String,
+        arguments: Vec<String>,
+        configuration: CommandConfiguration,
+    ) -> Self {
+        Self::try_from_parts(name, arguments, configuration).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// As [`Command::from_parts`]
+    ///
+    /// # Errors
+    ///
+    /// if the program does not start in `--rpc` mode
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn try_from_parts(
+        name: String,
+        arguments: Vec<String>,
+        configuration: CommandConfiguration,
+    ) -> Result<Self, String> {
+        let CommandConfiguration {
+            stdin_stdout_communication,
+            ignore_exit_code,
+            timeout,
+            startup_timeout,
+            limits,
+            pty,
+            keep_ansi,
+            working_directory,
+        } = configuration;
+
         let mut this = Self {
             name,
             arguments,
-            _ignore_exit_code,
+            _ignore_exit_code: ignore_exit_code,
             currently_running: None,
             timeout,
+            startup_timeout: startup_timeout.unwrap_or(DEFAULT_STARTUP_TIMEOUT),
+            limits,
+            pty,
+            keep_ansi,
+            working_directory,
+            directory: None,
         };
 
         // TODO bad
         if stdin_stdout_communication {
-            let running = this.spawn();
+            let running = this.try_spawn()?;
             this.currently_running = Some(running);
         }
-        this
+        Ok(this)
+    }
+
+    /// Applies limits and the pseudo-terminal. Returns a writer if `pipe_stdin`
+    fn start(
+        &self,
+        mut command: process::Command,
+        pipe_stdin: bool,
+    ) -> std::io::Result<(commands::Process, Option<Box<dyn Write + Send>>)> {
+        self.limits.apply(&mut command);
+
+        if let Some(size) = self.pty {
+            #[cfg(unix)]
+            {
+                let (process, writer) =
+                    commands::Process::spawn_pty(command, size, self.limits.output)?;
+                let writer: Box<dyn Write + Send> = Box::new(writer);
+                return Ok((process, pipe_stdin.then_some(writer)));
+            }
+            #[cfg(not(unix))]
+            {
+                let _ = size;
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "pseudo-terminals are only supported on unix",
+                ));
+            }
+        }
+
+        if pipe_stdin {
+            command.stdin(process::Stdio::piped());
+        }
+        let mut process = commands::Process::spawn_with_output_limit(command, self.limits.output)?;
+        let stdin = process.get_child_mut().stdin.take();
+        let writer = stdin.map(|stdin| Box::new(stdin) as Box<dyn Write + Send>);
+        Ok((process, writer))
+    }
+
+    /// After a crash or timeout
+    fn restart(&mut self) {
+        if let Some(Running { stdin, process, .. }) = self.currently_running.take() {
+            drop(stdin);
+            if let Err(err) = process.terminate(commands::TERMINATE_GRACE_PERIOD) {
+                eprintln!("could not terminate process: {err}");
+            }
+        }
+        let running = self.spawn();
+        let _ = self.currently_running.insert(running);
     }
 
     pub(crate) fn spawn(&self) -> Running {
+        self.try_spawn().unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Waits for the `start` message
+    fn try_spawn(&self) -> Result<Running, String> {
         let mut command = process::Command::new(&self.name);
         command.args(&self.arguments);
 
-        command.stdin(process::Stdio::piped());
-
-        let mut process = commands::Process::spawn(command).expect("could not spawn command");
+        let (mut process, stdin) = self
+            .start(command, true)
+            .map_err(|err| format!("could not spawn {name}: {err}", name = self.name))?;
+        let stdin = stdin.ok_or("Failed to open stdin")?;
 
         let child = process.get_child_mut();
-        let stdin = child.stdin.take().expect("Failed to open stdin");
-
         if let Ok(Some(status)) = child.try_wait() {
-            panic!("exited with: {status}");
+            return Err(format!("exited with: {status}"));
         }
 
-        // TODO duration temp
-        let (prelude, result) = process.read_timeout(time::Duration::from_secs(10), Some("start"));
+        let (mut prelude, result) = process.read_timeout(self.startup_timeout, Some("start"));
 
-        // Any prelude messages
-        for (channel, line) in prelude {
-            println!("prelude over: {line} ({channel:?})");
-        }
+        if let Ok(commands::ProcessStatus::Continuing) = result {
+            // Any prelude messages
+            for (channel, line) in prelude {
+                println!("prelude over: {line} ({channel:?})");
+            }
 
-        assert_eq!(
-            result.unwrap(),
-            commands::ProcessStatus::Continuing,
-            "process exected or timed-out"
-        );
+            Ok(Running {
+                stdin,
+                process,
+                setup: String::new(),
+            })
+        } else {
+            // `stderr` can still be being read after `stdout` has closed
+            let (rest, _) = process.read_timeout(time::Duration::from_millis(100), None);
+            prelude.extend(rest);
+            let _ = process.terminate(commands::TERMINATE_GRACE_PERIOD);
+            let lines: Vec<&str> = prelude.iter().map(|(_, line)| line.as_str()).collect();
+            let message = format!(
+                "process exited or timed-out before starting\n{lines}",
+                lines = lines.join("\n")
+            );
+            Err(message.trim_end().to_owned())
+        }
+    }
+}
 
-        Running { stdin, process }
+/// Name of the file written for `{file}`. The language of the code block is used as the extension
+#[must_use]
+pub fn case_file_name(test: &Test) -> String {
+    if test.language.is_empty() {
+        "spectra-case".to_owned()
+    } else {
+        format!("spectra-case.{language}", language = test.language)
     }
 }
 
 impl Runner for Command {
     fn run(&mut self, test: &Test) -> Result<(String, String), String> {
         if let Some(ref mut running) = self.currently_running {
-            for line in test.case.as_str().lines() {
+            if !(test.files.is_empty() && test.expected_files.is_empty()) {
+                return Err("files are not supported in `--rpc` mode".to_owned());
+            }
+
+            running.process.reset_output_count();
+
+            // Sent once (terminated by `setup`) rather than with every case. An empty setup
+            // resets the program when moving to a section without one
+            if running.setup != test.setup {
+                for line in test.setup.lines() {
+                    writeln!(running.stdin, "{line}").expect("could not write (early crash)");
+                }
+                writeln!(running.stdin, "setup").expect("could not write (early crash)");
+
+                let timeout = test.timeout.or(self.timeout).unwrap_or(time::Duration::MAX);
+                let (messages, result) = running.process.read_timeout(timeout, Some("end"));
+                if let Ok(commands::ProcessStatus::Continuing) = result {
+                    running.setup.clone_from(&test.setup);
+                } else {
+                    let lines: Vec<&str> = messages.iter().map(|(_, line)| line.as_str()).collect();
+                    let message = format!("SETUP FAILED\n{lines}", lines = lines.join("\n"));
+                    self.restart();
+                    return Err(message.trim_end().to_owned());
+                }
+            }
+
+            let mut case = test.case.as_str();
+            if !test.setup.is_empty()
+                && let Some(rest) = case.strip_prefix(test.setup.as_str())
+            {
+                case = rest.strip_prefix('\n').unwrap_or(rest);
+            }
+
+            for line in case.lines() {
                 // eprintln!("TEMP writing {line:?}");
                 writeln!(running.stdin, "{line}").expect("could not write (early crash)");
             }
 
             writeln!(running.stdin, "end").expect("could not write (early crash)");
 
-            let timeout = self.timeout.unwrap_or(time::Duration::MAX);
-            let (messages, res) = running.process.read_timeout(timeout, Some("end"));
+            let timeout = test.timeout.or(self.timeout).unwrap_or(time::Duration::MAX);
+            let (mut messages, res) = running.process.read_timeout(timeout, Some("end"));
+            if self.pty.is_some() && !self.keep_ansi {
+                strip_escapes(&mut messages);
+            }
 
             // TODO?
             // let is_err = messages
@@ -135,18 +358,29 @@ impl Runner for Command {
             //     .is_some_and(|(_, line)| line.starts_with("error: "));
 
             let mut timed_out = false;
+            let mut limit_reached = None;
 
             let command_no_longer_running: bool = match res {
                 Ok(status) => status == commands::ProcessStatus::Finished,
                 Err(err) => {
                     timed_out = err.kind() == std::io::ErrorKind::TimedOut;
-                    if timed_out {
-                        let _ = running.process.get_child_mut().kill();
+                    if err.kind() == std::io::ErrorKind::FileTooLarge {
+                        limit_reached = Some("output");
                     }
-                    timed_out
+                    // Process is terminated below
+                    timed_out || limit_reached.is_some()
                 }
             };
 
+            if command_no_longer_running
+                && !timed_out
+                && limit_reached.is_none()
+                && let Ok(status) = running.process.get_child_mut().wait()
+            {
+                let stderr = stderr_of(&messages);
+                limit_reached = self.limits.exceeded(status, &stderr);
+            }
+
             let (stdout, stderr) = {
                 use std::fmt::Write;
 
@@ -185,35 +419,93 @@ impl Runner for Command {
 
             if command_no_longer_running {
                 // eprintln!("restarting after timeout or crash {stdout:?} / {stderr:?}");
-                let running = self.spawn();
-                let _ = self.currently_running.insert(running);
+                self.restart();
             }
 
-            if command_no_longer_running {
+            if let Some(limit) = limit_reached {
+                let message = format!("RESOURCE LIMIT REACHED ({limit})\n{stdout}");
+                Err(message.trim_end().to_owned())
+            } else if command_no_longer_running {
                 // Rust prints crash messages to stdout
-                Err(if stdout.is_empty() { stderr } else { stdout })
+                Err(if stdout.is_empty() {
+                    stderr
+                } else if timed_out {
+                    format!("PROCESS TIMED OUT\n{stdout}")
+                } else {
+                    stdout
+                })
             } else {
                 Ok((stdout, stderr))
             }
         } else {
-            let arguments = self.arguments.iter().map(|argument| {
-                let argument = argument.as_str();
-                if let "{content}" = argument {
-                    // TODO should this be part of the markdown parser
-                    test.case.as_str().trim_end()
-                } else if let "{file}" = argument {
-                    todo!("create file")
-                } else {
-                    argument
-                }
-            });
+            use std::borrow::Cow;
+
+            // Files are written to a new directory, which is also `{dir}`. `{file}` is the case
+            // written to a file in that directory
+            let uses_directory = self
+                .arguments
+                .iter()
+                .chain(&self.working_directory)
+                .any(|argument| argument.contains("{dir}"));
+            let uses_file = self.arguments.iter().any(|argument| argument == "{file}");
+
+            let needs_directory = !(test.files.is_empty() && test.expected_files.is_empty());
+            let directory = if uses_directory || uses_file || needs_directory {
+                let directory = crate::utilities::TemporaryDirectory::new()
+                    .map_err(|err| format!("could not create directory: {err}"))?;
+                directory
+                    .write_files(&test.files)
+                    .map_err(|err| format!("could not write files: {err}"))?;
+                Some(directory)
+            } else {
+                None
+            };
+            let dir = directory
+                .as_ref()
+                .map(|directory| directory.path().display().to_string())
+                .unwrap_or_default();
+
+            let file = if let (true, Some(directory)) = (uses_file, &directory) {
+                let path = directory.path().join(case_file_name(test));
+                std::fs::write(&path, &test.case)
+                    .map_err(|err| format!("could not write case: {err}"))?;
+                path.display().to_string()
+            } else {
+                String::new()
+            };
 
-            let mut command = process::Command::new(&self.name);
-            command.args(arguments);
+            let arguments: Vec<Cow<str>> = self
+                .arguments
+                .iter()
+                .map(|argument| {
+                    let argument = argument.as_str();
+                    if let "{content}" = argument {
+                        // TODO should this be part of the markdown parser
+                        Cow::Borrowed(test.case.as_str().trim_end())
+                    } else if let "{file}" = argument {
+                        Cow::Borrowed(file.as_str())
+                    } else if argument.contains("{dir}") {
+                        Cow::Owned(argument.replace("{dir}", &dir))
+                    } else {
+                        Cow::Borrowed(argument)
+                    }
+                })
+                .collect();
 
-            let command = commands::Process::spawn(command).unwrap();
-            let timeout = self.timeout.unwrap_or(time::Duration::MAX);
-            let (messages, res) = command.read_timeout(timeout, None);
+            let mut command = process::Command::new(&self.name);
+            command.args(arguments.iter().map(AsRef::<str>::as_ref));
+            if let Some(ref working_directory) = self.working_directory {
+                command.current_dir(working_directory.replace("{dir}", &dir));
+            }
+            // Replaces (and so removes) the directory of the last test
+            self.directory = directory;
+
+            let (command, _) = self.start(command, false).unwrap();
+            let timeout = test.timeout.or(self.timeout).unwrap_or(time::Duration::MAX);
+            let (mut messages, res) = command.read_timeout(timeout, None);
+            if self.pty.is_some() && !self.keep_ansi {
+                strip_escapes(&mut messages);
+            }
 
             // TODO WIP
             // let is_err = messages
@@ -222,6 +514,21 @@ impl Runner for Command {
 
             match res {
                 Ok(_) => {
+                    let stderr = stderr_of(&messages);
+                    // Cleans up any descendants that are still running. The group is signalled
+                    // before the process is reaped
+                    let status = command.wait_then_terminate(commands::TERMINATE_GRACE_PERIOD);
+                    if let Err(ref err) = status {
+                        eprintln!("could not terminate process: {err}");
+                    }
+
+                    if let Ok(status) = status
+                        && let Some(limit) = self.limits.exceeded(status, &stderr)
+                    {
+                        let message = format!("RESOURCE LIMIT REACHED ({limit})\n{stderr}");
+                        return Err(message.trim_end().to_owned());
+                    }
+
                     if test.expected.is_none() && !messages.is_empty() {
                         eprintln!(
                             "Possibly unexpected stdout output {messages:?} from {name}",
@@ -250,13 +557,37 @@ impl Runner for Command {
 
                     Ok((stdout, stderr))
                 }
-                Err(err) => Err(format!("Command failed with {err:?}\n{messages:?}")),
+                Err(err) => {
+                    if let Err(err) = command.terminate(commands::TERMINATE_GRACE_PERIOD) {
+                        eprintln!("could not terminate process: {err}");
+                    }
+
+                    if err.kind() == std::io::ErrorKind::FileTooLarge {
+                        Err("RESOURCE LIMIT REACHED (output)".to_owned())
+                    } else {
+                        Err(format!("Command failed with {err:?}\n{messages:?}"))
+                    }
+                }
             }
         }
     }
 
+    fn directory(&self) -> Option<&std::path::Path> {
+        self.directory
+            .as_ref()
+            .map(crate::utilities::TemporaryDirectory::path)
+    }
+
+    /// Each test is a new process unless `--rpc`
+    fn keeps_state(&self) -> bool {
+        self.currently_running.is_some()
+    }
+
     fn close(self) {
-        if let Some(Running { mut stdin, process }) = self.currently_running {
+        if let Some(Running {
+            mut stdin, process, ..
+        }) = self.currently_running
+        {
             // Send the close signal
             writeln!(stdin, "close").unwrap();
 
@@ -267,11 +598,29 @@ impl Runner for Command {
                 println!("left over: {line} ({channel:?})");
             }
 
-            process.end().unwrap();
+            drop(stdin);
+            if let Err(err) = process.terminate(commands::TERMINATE_GRACE_PERIOD) {
+                eprintln!("could not terminate process: {err}");
+            }
         }
     }
 }
 
+fn strip_escapes(messages: &mut [(commands::Channel, String)]) {
+    for (_, message) in messages {
+        if let std::borrow::Cow::Owned(stripped) = crate::utilities::strip_ansi_escapes(message) {
+            *message = stripped;
+        }
+    }
+}
+
+fn stderr_of(messages: &[(commands::Channel, String)]) -> String {
+    let lines = messages.iter().filter_map(|(channel, line)| {
+        (*channel == commands::Channel::Stderr).then_some(line.as_str())
+    });
+    lines.collect::<Vec<_>>().join("\n")
+}
+
 pub type Identifier = usize;
 
 pub struct Timeout {
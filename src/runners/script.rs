@@ -0,0 +1,128 @@
+//! Runs a function from a Python or JavaScript module in a long-lived interpreter. An embedded
+//! adapter imports the module and handles the `start`/`end`/`close` protocol (as with `--rpc`), so
+//! the module only needs to export a function from the case to the output
+
+use crate::runners::program::{self, CommandConfiguration};
+use crate::{Runner, Test};
+
+use std::path::PathBuf;
+
+const PYTHON_ADAPTER: &str = include_str!("./adapters/adapter.py");
+const JAVASCRIPT_ADAPTER: &str = include_str!("./adapters/adapter.mjs");
+
+pub struct Script {
+    command: program::Command,
+    /// written to a temporary file, removed on close
+    adapter: PathBuf,
+}
+
+impl Script {
+    /// From `*path*::*function*` followed by `--interpreter *program*` (otherwise picked from the
+    /// extension) and other flags which are passed to [`program::Command`]
+    ///
+    /// # Errors
+    /// if the language of the module cannot be found or the adapter cannot be written
+    pub fn new_from_argument(
+        argument: &str,
+        configuration: CommandConfiguration,
+    ) -> Result<Self, String> {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+        let mut iter = crate::utilities::ArgumentIter::new(argument);
+        let Some(specification) = iter.next() else {
+            return Err("no path passed".to_owned());
+        };
+        let (path, name) = specification
+            .split_once("::")
+            .unwrap_or((&specification, "test"));
+
+        let mut interpreter = None;
+        let mut rest = Vec::new();
+        while let Some(flag) = iter.next() {
+            if flag == "--interpreter" {
+                let value = iter.next().ok_or("expected interpreter")?;
+                interpreter = Some(value.into_owned());
+            } else {
+                rest.push(flag);
+            }
+        }
+
+        let extension = std::path::Path::new(path)
+            .extension()
+            .and_then(std::ffi::OsStr::to_str)
+            .unwrap_or_default();
+
+        let (default_interpreter, adapter, adapter_extension) = match extension {
+            "py" => ("python3", PYTHON_ADAPTER, "py"),
+            "js" | "mjs" | "cjs" => ("node", JAVASCRIPT_ADAPTER, "mjs"),
+            "ts" | "mts" => ("bun", JAVASCRIPT_ADAPTER, "mjs"),
+            extension => {
+                return Err(format!(
+                    "unknown script extension {extension:?} (expected py, js or ts)"
+                ));
+            }
+        };
+        let interpreter = interpreter.unwrap_or_else(|| default_interpreter.to_owned());
+
+        // Unique so that runners do not overwrite (or remove) each other's adapter
+        let count = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let adapter_path = std::env::temp_dir().join(format!(
+            "spectra-adapter-{pid}-{count}.{adapter_extension}",
+            pid = std::process::id()
+        ));
+        std::fs::write(&adapter_path, adapter)
+            .map_err(|err| format!("could not write adapter: {err}"))?;
+
+        let mut argument = format!(
+            "{interpreter} \"{adapter}\" \"{path}\" \"{name}\" --rpc",
+            adapter = adapter_path.display()
+        );
+        for flag in rest {
+            argument.push(' ');
+            argument.push_str(&flag);
+        }
+
+        let command = program::Command::new_with_configuration(&argument, configuration);
+        Ok(Self {
+            command,
+            adapter: adapter_path,
+        })
+    }
+}
+
+impl Runner for Script {
+    fn run(&mut self, test: &Test) -> Result<(String, String), String> {
+        // The adapter calls the function with the setup as part of the case
+        let test = Test {
+            setup: String::new(),
+            ..test.clone()
+        };
+        let (stdout, stderr) = self.command.run(&test)?;
+
+        let mut lines = stdout.lines();
+        let header = lines.next().unwrap_or_default();
+        let Some((tag, count)) = header
+            .split_once(' ')
+            .and_then(|(tag, count)| Some((tag, count.parse::<usize>().ok()?)))
+        else {
+            return Err(format!("unexpected response from adapter {stdout:?}"));
+        };
+
+        let content: Vec<&str> = lines.by_ref().take(count).collect();
+        let content = content.join("\n");
+        let captured: Vec<&str> = lines.chain(stderr.lines()).collect();
+        let captured = captured.join("\n");
+
+        match tag {
+            "ok" => Ok((content, captured)),
+            "err" if captured.is_empty() => Err(content),
+            "err" => Err(format!("{content}\n{captured}")),
+            _ => Err(format!("unexpected response from adapter {stdout:?}")),
+        }
+    }
+
+    fn close(self) {
+        self.command.close();
+        let _ = std::fs::remove_file(self.adapter);
+    }
+}
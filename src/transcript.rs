@@ -0,0 +1,320 @@
+//! Shell transcripts: code blocks tagged `console` or `shell-session`, where `$ ` lines are
+//! commands and the lines after them are the expected output (`stdout` and `stderr` combined).
+//! A non-zero exit code is written as `[*code*]` after the output
+//!
+//! ```console
+//! $ echo hi > file.txt
+//! $ cat file.txt
+//! hi
+//! $ cat missing.txt
+//! cat: missing.txt: No such file or directory
+//! [1]
+//! ```
+//!
+//! Each block is run in a new (empty) directory by a single shell, so `cd`, `export` and
+//! variables carry over to the following commands. `SPECTRA_ROOT` is set to the directory spectra
+//! was run in. On Windows each command is instead run by a new `cmd`
+
+use crate::Test;
+use crate::utilities::{Normalisation, TemporaryDirectory, commands};
+
+use std::fmt::Write;
+
+/// Languages of code blocks which are transcripts
+pub const LANGUAGES: &[&str] = &["console", "shell-session"];
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Step {
+    pub command: String,
+    pub expected: String,
+    pub exit_code: i32,
+}
+
+#[must_use]
+pub fn parse(on: &str) -> Vec<Step> {
+    let mut steps: Vec<Step> = Vec::new();
+    for line in on.lines() {
+        if let Some(command) = line.strip_prefix("$ ").or((line == "$").then_some("")) {
+            steps.push(Step {
+                command: command.to_owned(),
+                ..Step::default()
+            });
+        } else if let Some(step) = steps.last_mut() {
+            // Continuation of a multi-line command
+            if let Some(rest) = line.strip_prefix("> ")
+                && step.expected.is_empty()
+            {
+                step.command.push('\n');
+                step.command.push_str(rest);
+            } else {
+                step.expected.push_str(line);
+                step.expected.push('\n');
+            }
+        }
+    }
+
+    // The exit code is only the last line of the output
+    for step in &mut steps {
+        let mut lines = step.expected.lines();
+        if let Some(exit_code) = lines
+            .next_back()
+            .and_then(|line| line.strip_prefix('['))
+            .and_then(|line| line.strip_suffix(']'))
+            .and_then(|code| code.parse().ok())
+        {
+            step.exit_code = exit_code;
+            let length = lines.map(|line| line.len() + 1).sum();
+            step.expected.truncate(length);
+        }
+    }
+    steps
+}
+
+/// Runs the steps in `test.case`
+///
+/// # Errors
+/// returns a diff for each command whose output or exit code did not match
+pub fn run(test: &Test, normalisation: &Normalisation) -> Result<(), String> {
+    let sandbox =
+        TemporaryDirectory::new().map_err(|err| format!("could not create sandbox: {err}"))?;
+    sandbox
+        .write_files(&test.files)
+        .map_err(|err| format!("could not write files: {err}"))?;
+    let root = std::env::current_dir().unwrap_or_default();
+    let mut shell = Shell::new(sandbox.path(), &root)?;
+
+    let mut failures = String::new();
+    for step in parse(&test.case) {
+        let (output, exit_code) = match shell.execute(&step.command, test.timeout) {
+            Ok(result) => result,
+            // Such as after an `exit`
+            Err(err) => {
+                writeln!(failures, "$ {command}\n{err}", command = step.command).unwrap();
+                break;
+            }
+        };
+
+        let output = normalisation.apply(&output);
+        let expected = normalisation.apply(&step.expected);
+        let (output, expected) = (output.trim_end(), expected.trim_end());
+
+        let output_matches = crate::utilities::is_equal_ignore_new_line_sequence(output, expected);
+        if output_matches && exit_code == Some(step.exit_code) {
+            continue;
+        }
+
+        writeln!(failures, "$ {command}", command = step.command).unwrap();
+        if !output_matches {
+            let comparison = pretty_assertions::StrComparison::new(expected, output);
+            writeln!(failures, "{comparison}").unwrap();
+        }
+        match exit_code {
+            Some(exit_code) if exit_code != step.exit_code => {
+                let expected = step.exit_code;
+                writeln!(failures, "expected exit code {expected}, found {exit_code}").unwrap();
+            }
+            Some(_) => {}
+            None => {
+                writeln!(failures, "PROCESS TIMED OUT").unwrap();
+                // The following commands would run in a different shell
+                break;
+            }
+        }
+    }
+    shell.close();
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.trim_end().to_owned())
+    }
+}
+
+/// Runs commands one after another in the same shell
+#[cfg(unix)]
+struct Shell {
+    /// `None` once the shell has exited
+    process: Option<(commands::Process, std::process::ChildStdin)>,
+    /// printed after the exit code of each command
+    marker: String,
+}
+
+#[cfg(unix)]
+impl Shell {
+    fn new(directory: &std::path::Path, root: &std::path::Path) -> Result<Self, String> {
+        let mut command = std::process::Command::new("sh");
+        command
+            .current_dir(directory)
+            .env("SPECTRA_ROOT", root)
+            .stdin(std::process::Stdio::piped());
+
+        let mut process =
+            commands::Process::spawn(command).map_err(|err| format!("could not run sh: {err}"))?;
+        let stdin = process.get_child_mut().stdin.take().ok_or("could not open stdin")?;
+
+        let nanoseconds = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let marker = format!("spectra-end-{nanoseconds}");
+
+        let mut shell = Self {
+            process: Some((process, stdin)),
+            marker,
+        };
+        shell.write("exec 2>&1\n")?;
+        Ok(shell)
+    }
+
+    fn write(&mut self, content: &str) -> Result<(), String> {
+        use std::io::Write;
+
+        let Some((_, stdin)) = &mut self.process else {
+            return Err("shell has exited".to_owned());
+        };
+        stdin
+            .write_all(content.as_bytes())
+            .and_then(|()| stdin.flush())
+            .map_err(|err| format!("could not write to shell: {err}"))
+    }
+
+    /// Returns the combined output and the exit code (`None` if timed out). Commands do not
+    /// read the rest of the transcript as their input
+    fn execute(
+        &mut self,
+        command: &str,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<(String, Option<i32>), String> {
+        let marker = self.marker.clone();
+        let script = format!("{{\n{command}\n}} </dev/null\nprintf '\\n%s\\n{marker}\\n' \"$?\"\n");
+        self.write(&script)?;
+
+        let Some((process, _)) = &self.process else {
+            return Err("shell has exited".to_owned());
+        };
+        let timeout = timeout.unwrap_or(std::time::Duration::MAX);
+        let (mut messages, result) = process.read_timeout(timeout, Some(&marker));
+
+        match result {
+            Ok(commands::ProcessStatus::Continuing) => {
+                let exit_code = messages.pop().and_then(|(_, code)| code.parse().ok());
+                Ok((output_of(messages), Some(exit_code.unwrap_or(-1))))
+            }
+            // For example after `exit`
+            Ok(commands::ProcessStatus::Finished) => {
+                let (process, stdin) = self.process.take().unwrap();
+                drop(stdin);
+                let status = process
+                    .end()
+                    .map_err(|err| format!("could not run {command:?}: {err}"))?;
+                Ok((output_of(messages), Some(status.code().unwrap_or(-1))))
+            }
+            Err(_) => {
+                self.close();
+                Ok((output_of(messages), None))
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some((process, stdin)) = self.process.take() {
+            drop(stdin);
+            let _ = process.terminate(commands::TERMINATE_GRACE_PERIOD);
+        }
+    }
+}
+
+/// Runs each command in a new `cmd`
+#[cfg(not(unix))]
+struct Shell {
+    directory: std::path::PathBuf,
+    root: std::path::PathBuf,
+}
+
+#[cfg(not(unix))]
+impl Shell {
+    fn new(directory: &std::path::Path, root: &std::path::Path) -> Result<Self, String> {
+        Ok(Self {
+            directory: directory.to_owned(),
+            root: root.to_owned(),
+        })
+    }
+
+    /// Returns the combined output and the exit code (`None` if timed out)
+    fn execute(
+        &mut self,
+        command: &str,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<(String, Option<i32>), String> {
+        let mut process = std::process::Command::new("cmd");
+        process
+            .arg("/C")
+            .arg(format!("({command}) 2>&1"))
+            .current_dir(&self.directory)
+            .env("SPECTRA_ROOT", &self.root)
+            .stdin(std::process::Stdio::null());
+
+        let process = commands::Process::spawn(process)
+            .map_err(|err| format!("could not run {command:?}: {err}"))?;
+
+        let timeout = timeout.unwrap_or(std::time::Duration::MAX);
+        let (messages, result) = process.read_timeout(timeout, None);
+
+        if result.is_err() {
+            let _ = process.terminate(commands::TERMINATE_GRACE_PERIOD);
+            return Ok((output_of(messages), None));
+        }
+
+        let status = process
+            .end()
+            .map_err(|err| format!("could not run {command:?}: {err}"))?;
+        Ok((output_of(messages), Some(status.code().unwrap_or(-1))))
+    }
+
+    fn close(&mut self) {}
+}
+
+fn output_of(messages: Vec<(commands::Channel, String)>) -> String {
+    let mut output = String::new();
+    for (_channel, message) in messages {
+        output.push_str(&message);
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps() {
+        let transcript =
+            "$ echo hi\nhi\n$ false\n[1]\n$ for i in 1 2; do\n>   echo $i\n> done\n1\n2\n$ cat a\n[3]\nb\n";
+        assert_eq!(
+            parse(transcript),
+            vec![
+                Step {
+                    command: "echo hi".to_owned(),
+                    expected: "hi\n".to_owned(),
+                    exit_code: 0
+                },
+                Step {
+                    command: "false".to_owned(),
+                    expected: String::new(),
+                    exit_code: 1
+                },
+                Step {
+                    command: "for i in 1 2; do\n  echo $i\ndone".to_owned(),
+                    expected: "1\n2\n".to_owned(),
+                    exit_code: 0
+                },
+                Step {
+                    command: "cat a".to_owned(),
+                    expected: "[3]\nb\n".to_owned(),
+                    exit_code: 0
+                },
+            ]
+        );
+    }
+}
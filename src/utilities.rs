@@ -22,9 +22,206 @@ pub fn is_equal_ignore_new_line_sequence(lhs: &str, rhs: &str) -> bool {
     }
 }
 
+/// Removes ANSI escape sequences (colours, cursor movement, OSC such as titles and links)
+#[must_use]
+pub fn strip_ansi_escapes(on: &str) -> Cow<'_, str> {
+    if !on.contains('\x1b') {
+        return Cow::Borrowed(on);
+    }
+
+    let mut buf = String::with_capacity(on.len());
+    let mut chars = on.chars().peekable();
+    while let Some(chr) = chars.next() {
+        if chr != '\x1b' {
+            buf.push(chr);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters until a final byte in `@..=~`
+            Some('[') => {
+                for chr in chars.by_ref() {
+                    if ('@'..='~').contains(&chr) {
+                        break;
+                    }
+                }
+            }
+            // OSC: until BEL or ST (`ESC \`)
+            Some(']') => {
+                while let Some(chr) = chars.next() {
+                    if chr == '\x07' {
+                        break;
+                    }
+                    if chr == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // Other two character sequences
+            Some(_) | None => {}
+        }
+    }
+    Cow::Owned(buf)
+}
+
+/// Parses durations such as `5s`, `250ms` or `1m`. Numbers without a unit are milliseconds
+#[must_use]
+pub fn parse_duration(on: &str) -> Option<std::time::Duration> {
+    let on = on.trim();
+    let (value, multiplier) = if let Some(value) = on.strip_suffix("ms") {
+        (value, 1)
+    } else if let Some(value) = on.strip_suffix('s') {
+        (value, 1000)
+    } else if let Some(value) = on.strip_suffix('m') {
+        (value, 60 * 1000)
+    } else {
+        (on, 1)
+    };
+    let value: u64 = value.trim_end().parse().ok()?;
+    value
+        .checked_mul(multiplier)
+        .map(std::time::Duration::from_millis)
+}
+
+/// Parses sizes such as `512MB`, `64KB` or `1GB` (as multiples of 1024). Numbers without a unit are bytes
+#[must_use]
+pub fn parse_size(on: &str) -> Option<u64> {
+    let on = on.trim();
+    let (value, multiplier) = if let Some(value) = on.strip_suffix("GB") {
+        (value, 1024 * 1024 * 1024)
+    } else if let Some(value) = on.strip_suffix("MB") {
+        (value, 1024 * 1024)
+    } else if let Some(value) = on.strip_suffix("KB") {
+        (value, 1024)
+    } else if let Some(value) = on.strip_suffix('B') {
+        (value, 1)
+    } else {
+        (on, 1)
+    };
+    let value: u64 = value.trim_end().parse().ok()?;
+    value.checked_mul(multiplier)
+}
+
+/// Creates a file in the temporary directory which is removed from the file system straight away
+/// (so it is cleaned up once dropped)
+///
+/// # Errors
+/// if the file cannot be created
+pub fn temporary_file() -> std::io::Result<std::fs::File> {
+    static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    let count = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let name = format!("spectra-{pid}-{count}", pid = std::process::id());
+    let path = std::env::temp_dir().join(name);
+    let file = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    std::fs::remove_file(&path)?;
+    Ok(file)
+}
+
+/// A new directory in the temporary directory which is removed (with its contents) once dropped
+pub struct TemporaryDirectory(std::path::PathBuf);
+
+impl TemporaryDirectory {
+    /// # Errors
+    /// if the directory cannot be created
+    pub fn new() -> std::io::Result<Self> {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+        let count = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let name = format!("spectra-{pid}-directory-{count}", pid = std::process::id());
+        let path = std::env::temp_dir().join(name);
+        std::fs::create_dir(&path)?;
+        Ok(Self(path))
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+
+    /// Writes `(path, content)` pairs into the directory, creating parent directories as needed
+    ///
+    /// # Errors
+    /// if a path is not relative to the directory or a file cannot be written
+    pub fn write_files(&self, files: &[(String, String)]) -> std::io::Result<()> {
+        use std::path::{Component, Path};
+
+        for (path, content) in files {
+            let relative = Path::new(path);
+            let inside = relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+            if !inside {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{path:?} is not a relative path inside the directory"),
+                ));
+            }
+
+            let path = self.0.join(relative);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, content)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TemporaryDirectory {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Transformations applied to both the output and the expected output before comparing
+#[derive(Debug, Default, Clone)]
+pub struct Normalisation {
+    pub trim_trailing_whitespace: bool,
+    /// removes lines that end in some substring
+    pub strip_lines_ending_with: Vec<String>,
+}
+
+impl Normalisation {
+    #[must_use]
+    pub fn apply<'a>(&self, on: &'a str) -> Cow<'a, str> {
+        if !self.trim_trailing_whitespace && self.strip_lines_ending_with.is_empty() {
+            return Cow::Borrowed(on);
+        }
+
+        let mut buf = String::with_capacity(on.len());
+        for line in on.lines() {
+            let strip = self
+                .strip_lines_ending_with
+                .iter()
+                .any(|suffix| line.ends_with(suffix.as_str()));
+
+            if !strip {
+                if self.trim_trailing_whitespace {
+                    buf.push_str(line.trim_end());
+                } else {
+                    buf.push_str(line);
+                }
+                buf.push('\n');
+            }
+        }
+        Cow::Owned(buf)
+    }
+}
+
 pub mod filter {
     pub trait Filter {
         fn should_skip(&self, s: &str) -> bool;
+
+        /// For a case of a group (see [`crate::TestGroup::case_name`]). By default the case
+        /// is run if either name is
+        fn should_skip_case(&self, group: &str, case: &str) -> bool {
+            self.should_skip(group) && self.should_skip(case)
+        }
     }
 
     #[derive(Debug, Clone)]
@@ -40,10 +237,24 @@ pub mod filter {
                 case_sensitive: self.case_sensitive,
                 ..glob::MatchOptions::default()
             };
-            let result = self.matcher.matches_with(name, options);
+            // Exact names, such as those with `[case 2/5]`, also match
+            let result = self.matcher.matches_with(name, options)
+                || if self.case_sensitive {
+                    self.matcher.as_str() == name
+                } else {
+                    self.matcher.as_str().eq_ignore_ascii_case(name)
+                };
 
             if self.positive { !result } else { result }
         }
+
+        fn should_skip_case(&self, group: &str, case: &str) -> bool {
+            if self.positive {
+                self.should_skip(group) && self.should_skip(case)
+            } else {
+                self.should_skip(group) || self.should_skip(case)
+            }
+        }
     }
 }
 
@@ -61,6 +272,7 @@ pub mod commands {
     pub enum ProcessNotification {
         Message(Channel, String),
         Completed, // (ExitStatus),
+        OutputLimitReached,
     }
 
     #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
@@ -69,15 +281,155 @@ pub mod commands {
         Continuing,
     }
 
+    /// Limits for spawned processes. Everything other than `output` is only supported on unix
+    /// (using `setrlimit`). Note these apply for the lifetime of the process, so in `--rpc` mode
+    /// they are across all tests run before a restart
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct ResourceLimits {
+        /// in bytes
+        pub address_space: Option<u64>,
+        pub cpu_seconds: Option<u64>,
+        pub open_files: Option<u64>,
+        /// Note this is per user rather than per process tree
+        pub processes: Option<u64>,
+        /// in bytes, across `stdout` and `stderr`
+        pub output: Option<usize>,
+    }
+
+    impl ResourceLimits {
+        #[cfg(unix)]
+        pub fn apply(&self, command: &mut process::Command) {
+            use std::os::unix::process::CommandExt;
+
+            let limits = [
+                (libc::RLIMIT_AS, self.address_space, 0),
+                // Hard limit is higher so that `SIGXCPU` is sent first
+                (libc::RLIMIT_CPU, self.cpu_seconds, 1),
+                (libc::RLIMIT_NOFILE, self.open_files, 0),
+                (libc::RLIMIT_NPROC, self.processes, 0),
+            ];
+
+            if limits.iter().all(|(_, value, _)| value.is_none()) {
+                return;
+            }
+
+            // SAFETY: `setrlimit` is async-signal-safe and nothing is allocated
+            unsafe {
+                command.pre_exec(move || {
+                    for (resource, value, hard_extra) in limits {
+                        if let Some(value) = value {
+                            let limit = libc::rlimit {
+                                rlim_cur: value as libc::rlim_t,
+                                rlim_max: (value + hard_extra) as libc::rlim_t,
+                            };
+                            if libc::setrlimit(resource, &raw const limit) != 0 {
+                                return Err(io::Error::last_os_error());
+                            }
+                        }
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        #[cfg(not(unix))]
+        pub fn apply(&self, _command: &mut process::Command) {
+            let unsupported = self.address_space.is_some()
+                || self.cpu_seconds.is_some()
+                || self.open_files.is_some()
+                || self.processes.is_some();
+
+            if unsupported {
+                eprintln!("resource limits are not supported on this platform");
+            }
+        }
+
+        /// Guesses which limit (if any) caused the process to exit
+        #[must_use]
+        pub fn exceeded(&self, status: process::ExitStatus, stderr: &str) -> Option<&'static str> {
+            #[cfg(unix)]
+            {
+                use std::os::unix::process::ExitStatusExt;
+
+                let signal = status.signal();
+                if self.cpu_seconds.is_some()
+                    && matches!(signal, Some(libc::SIGXCPU | libc::SIGKILL))
+                {
+                    return Some("cpu");
+                }
+                let memory_signal =
+                    matches!(signal, Some(libc::SIGABRT | libc::SIGSEGV | libc::SIGBUS));
+                if self.address_space.is_some() && (memory_signal || is_out_of_memory(stderr)) {
+                    return Some("memory");
+                }
+            }
+            #[cfg(not(unix))]
+            let _ = status;
+
+            if self.open_files.is_some() && stderr.contains("Too many open files") {
+                Some("open files")
+            } else if self.processes.is_some()
+                && stderr.contains("Resource temporarily unavailable")
+            {
+                Some("processes")
+            } else {
+                None
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PtySize {
+        pub columns: u16,
+        pub rows: u16,
+    }
+
+    impl Default for PtySize {
+        fn default() -> Self {
+            Self {
+                columns: 80,
+                rows: 24,
+            }
+        }
+    }
+
+    impl std::str::FromStr for PtySize {
+        type Err = ();
+
+        /// From `*columns*x*rows*`
+        fn from_str(on: &str) -> Result<Self, Self::Err> {
+            let (columns, rows) = on.split_once('x').ok_or(())?;
+            Ok(Self {
+                columns: columns.trim().parse().map_err(|_| ())?,
+                rows: rows.trim().parse().map_err(|_| ())?,
+            })
+        }
+    }
+
     pub struct Process {
         child: process::Child,
         stdout_handle: thread::JoinHandle<()>,
-        stderr_handle: thread::JoinHandle<()>,
+        /// `None` when using a pseudo-terminal (as `stderr` is combined with `stdout`)
+        stderr_handle: Option<thread::JoinHandle<()>>,
         receiver: sync::mpsc::Receiver<ProcessNotification>,
+        output: sync::Arc<OutputCounter>,
     }
 
     impl Process {
-        pub fn spawn(mut command: process::Command) -> io::Result<Self> {
+        pub fn spawn(command: process::Command) -> io::Result<Self> {
+            Self::spawn_with_output_limit(command, None)
+        }
+
+        /// Once more than `output_limit` bytes have been read, a [`ProcessNotification::OutputLimitReached`]
+        /// is sent and further output is dropped
+        pub fn spawn_with_output_limit(
+            mut command: process::Command,
+            output_limit: Option<usize>,
+        ) -> io::Result<Self> {
+            // So that the whole tree can be signalled in `terminate`
+            #[cfg(unix)]
+            std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
             let mut child = command
                 .stdout(process::Stdio::piped())
                 .stderr(process::Stdio::piped())
@@ -88,12 +440,27 @@ pub mod commands {
 
             let (sender, receiver) = sync::mpsc::sync_channel::<ProcessNotification>(0);
 
+            let output = sync::Arc::new(OutputCounter {
+                limit: output_limit,
+                ..OutputCounter::default()
+            });
+
             // Thread to read `stdout`
             let sender_stdout = sender.clone();
+            let output_stdout = output.clone();
             let stdout_handle = thread::spawn(move || {
                 for line in stdout.lines().map_while(Result::ok) {
-                    // TODO `expect` here
-                    let _ = sender_stdout.send(ProcessNotification::Message(Channel::Stdout, line));
+                    match output_stdout.add(&line) {
+                        OutputCount::Within => {
+                            // TODO `expect` here
+                            let _ = sender_stdout
+                                .send(ProcessNotification::Message(Channel::Stdout, line));
+                        }
+                        OutputCount::Reached => {
+                            let _ = sender_stdout.send(ProcessNotification::OutputLimitReached);
+                        }
+                        OutputCount::Over => {}
+                    }
                 }
 
                 // TODO `expect` here
@@ -102,23 +469,141 @@ pub mod commands {
 
             // Thread to read `stderr`
             let sender_stderr = sender; // .clone();
+            let output_stderr = output.clone();
             let stderr_handle = thread::spawn(move || {
                 for line in stderr.lines().map_while(Result::ok) {
-                    // TODO `expect` here
-                    sender_stderr
-                        .send(ProcessNotification::Message(Channel::Stderr, line))
-                        .expect("Failed to send stderr");
+                    match output_stderr.add(&line) {
+                        OutputCount::Within => {
+                            // Fails if the process has been terminated
+                            let _ = sender_stderr
+                                .send(ProcessNotification::Message(Channel::Stderr, line));
+                        }
+                        OutputCount::Reached => {
+                            let _ = sender_stderr.send(ProcessNotification::OutputLimitReached);
+                        }
+                        OutputCount::Over => {}
+                    }
                 }
             });
 
             Ok(Self {
                 child,
                 stdout_handle,
-                stderr_handle,
+                stderr_handle: Some(stderr_handle),
                 receiver,
+                output,
             })
         }
 
+        /// Spawns with `stdin`, `stdout` and `stderr` attached to a new pseudo-terminal. Output is
+        /// read as [`Channel::Stdout`]. Returns the writer for input (which is not echoed back)
+        #[cfg(unix)]
+        pub fn spawn_pty(
+            mut command: process::Command,
+            size: PtySize,
+            output_limit: Option<usize>,
+        ) -> io::Result<(Self, std::fs::File)> {
+            use std::os::fd::{FromRawFd, OwnedFd};
+            use std::os::unix::process::CommandExt;
+
+            let mut master: libc::c_int = -1;
+            let mut slave: libc::c_int = -1;
+            let mut window_size = libc::winsize {
+                ws_row: size.rows,
+                ws_col: size.columns,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            };
+
+            // SAFETY: pointers are valid for the duration of the call and the file descriptors
+            // are owned from here on
+            let (master, slave) = unsafe {
+                let result = libc::openpty(
+                    &raw mut master,
+                    &raw mut slave,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    &raw mut window_size,
+                );
+                if result != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave))
+            };
+
+            // Do not echo input back as output
+            // SAFETY: `termios` is only read after `tcgetattr` succeeds
+            unsafe {
+                use std::os::fd::AsRawFd;
+
+                let mut termios = std::mem::zeroed::<libc::termios>();
+                if libc::tcgetattr(slave.as_raw_fd(), &raw mut termios) == 0 {
+                    termios.c_lflag &= !libc::ECHO;
+                    libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &raw const termios);
+                }
+            }
+
+            command
+                .stdin(process::Stdio::from(slave.try_clone()?))
+                .stdout(process::Stdio::from(slave.try_clone()?))
+                .stderr(process::Stdio::from(slave));
+
+            // New session (and so process group) with the terminal as the controlling terminal
+            // SAFETY: `setsid` and `ioctl` are async-signal-safe
+            unsafe {
+                command.pre_exec(|| {
+                    if libc::setsid() == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    if libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+
+            let child = command.spawn()?;
+            // Drop our copies of `slave` so that reading ends when the process does
+            drop(command);
+
+            let writer = std::fs::File::from(master.try_clone()?);
+            let reader = BufReader::new(std::fs::File::from(master));
+
+            let (sender, receiver) = sync::mpsc::sync_channel::<ProcessNotification>(0);
+            let output = sync::Arc::new(OutputCounter {
+                limit: output_limit,
+                ..OutputCounter::default()
+            });
+
+            let output_stdout = output.clone();
+            let stdout_handle = thread::spawn(move || {
+                // Reading errors (`EIO`) once the terminal is closed
+                for line in reader.lines().map_while(Result::ok) {
+                    match output_stdout.add(&line) {
+                        OutputCount::Within => {
+                            let _ =
+                                sender.send(ProcessNotification::Message(Channel::Stdout, line));
+                        }
+                        OutputCount::Reached => {
+                            let _ = sender.send(ProcessNotification::OutputLimitReached);
+                        }
+                        OutputCount::Over => {}
+                    }
+                }
+
+                let _ = sender.send(ProcessNotification::Completed);
+            });
+
+            let this = Self {
+                child,
+                stdout_handle,
+                stderr_handle: None,
+                receiver,
+                output,
+            };
+            Ok((this, writer))
+        }
+
         pub fn read_timeout(
             &self,
             timeout: time::Duration,
@@ -139,6 +624,13 @@ pub mod commands {
                         ProcessNotification::Completed => {
                             return (messages, Ok(ProcessStatus::Finished));
                         }
+                        ProcessNotification::OutputLimitReached => {
+                            let result = Err(io::Error::new(
+                                io::ErrorKind::FileTooLarge,
+                                "output limit reached",
+                            ));
+                            return (messages, result);
+                        }
                     },
                     Err(_timeout) => {
                         let result = Err(io::Error::new(
@@ -156,10 +648,78 @@ pub mod commands {
         pub fn end(mut self) -> io::Result<process::ExitStatus> {
             let status = self.child.wait()?;
             self.stdout_handle.join().unwrap();
-            self.stderr_handle.join().unwrap();
+            if let Some(stderr_handle) = self.stderr_handle {
+                stderr_handle.join().unwrap();
+            }
             Ok(status)
         }
 
+        /// Sends `SIGTERM` to the process group (including grandchildren such as the program run by
+        /// `cargo run`) then `SIGKILL` after `grace`. Errors if the reader threads do not finish (for
+        /// example if a process has left the group and is holding the pipes open)
+        pub fn terminate(self, grace: time::Duration) -> io::Result<process::ExitStatus> {
+            let Self {
+                mut child,
+                stdout_handle,
+                stderr_handle,
+                receiver,
+                output: _,
+            } = self;
+
+            // Readers may be blocked sending
+            drop(receiver);
+
+            signal_group(&mut child, Signal::Terminate);
+
+            let deadline = time::Instant::now() + grace;
+            while !has_exited(&mut child, false)? {
+                if time::Instant::now() >= deadline {
+                    signal_group(&mut child, Signal::Kill);
+                    has_exited(&mut child, true)?;
+                    break;
+                }
+                thread::sleep(time::Duration::from_millis(10));
+            }
+
+            // Descendants may have outlived the leader. As the leader has not been reaped yet, the
+            // group id cannot have been reused
+            signal_group(&mut child, Signal::Kill);
+            let status = child.wait()?;
+
+            let deadline = time::Instant::now() + grace;
+            let finished = |handle: &thread::JoinHandle<()>| handle.is_finished();
+            while !(finished(&stdout_handle) && stderr_handle.as_ref().is_none_or(finished)) {
+                if time::Instant::now() >= deadline {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "output of process still open after terminating process group",
+                    ));
+                }
+                thread::sleep(time::Duration::from_millis(10));
+            }
+            let _ = stdout_handle.join();
+            if let Some(stderr_handle) = stderr_handle {
+                let _ = stderr_handle.join();
+            }
+
+            Ok(status)
+        }
+
+        /// Waits for the process to exit by itself and then terminates the rest of its group
+        pub fn wait_then_terminate(
+            mut self,
+            grace: time::Duration,
+        ) -> io::Result<process::ExitStatus> {
+            has_exited(&mut self.child, true)?;
+            self.terminate(grace)
+        }
+
+        /// Starts counting towards the output limit again. For processes which run several
+        /// tests (`--rpc`), so that the limit applies to each test
+        pub fn reset_output_count(&self) {
+            self.output.total.store(0, sync::atomic::Ordering::Relaxed);
+        }
+
         pub fn get_child_mut(&mut self) -> &mut process::Child {
             &mut self.child
         }
@@ -173,6 +733,101 @@ pub mod commands {
             }
         }
     }
+
+    /// Time between `SIGTERM` and `SIGKILL` in [`Process::terminate`]
+    pub const TERMINATE_GRACE_PERIOD: time::Duration = time::Duration::from_millis(500);
+
+    #[derive(Clone, Copy)]
+    enum Signal {
+        Terminate,
+        Kill,
+    }
+
+    #[cfg(unix)]
+    fn signal_group(child: &mut process::Child, signal: Signal) {
+        let signal = match signal {
+            Signal::Terminate => libc::SIGTERM,
+            Signal::Kill => libc::SIGKILL,
+        };
+        if let Ok(group) = libc::pid_t::try_from(child.id()) {
+            // SAFETY: negative pid signals the group created in `spawn_with_output_limit`
+            unsafe {
+                libc::kill(-group, signal);
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn signal_group(child: &mut process::Child, _signal: Signal) {
+        let _ = child.kill();
+    }
+
+    /// Without reaping the process, so that its id (and so the id of its group) is not reused
+    /// before the group is signalled. Waits for it to exit if `block`
+    #[cfg(unix)]
+    fn has_exited(child: &mut process::Child, block: bool) -> io::Result<bool> {
+        let mut options = libc::WEXITED | libc::WNOWAIT;
+        if !block {
+            options |= libc::WNOHANG;
+        }
+        // SAFETY: `pid` is a child of this process and `info` is written by `waitid`
+        unsafe {
+            let mut info: libc::siginfo_t = std::mem::zeroed();
+            if libc::waitid(libc::P_PID, child.id(), &raw mut info, options) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            // Left zeroed if the process is still running
+            Ok(info.si_signo != 0)
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn has_exited(child: &mut process::Child, block: bool) -> io::Result<bool> {
+        if block {
+            child.wait().map(|_| true)
+        } else {
+            child.try_wait().map(|status| status.is_some())
+        }
+    }
+
+    #[cfg(unix)]
+    fn is_out_of_memory(stderr: &str) -> bool {
+        // Rust, C and Python messages
+        ["memory allocation of", "out of memory", "MemoryError"]
+            .iter()
+            .any(|message| stderr.contains(message))
+    }
+
+    /// Shared between the `stdout` and `stderr` threads
+    #[derive(Default)]
+    struct OutputCounter {
+        limit: Option<usize>,
+        total: sync::atomic::AtomicUsize,
+    }
+
+    enum OutputCount {
+        Within,
+        /// First line over the limit
+        Reached,
+        Over,
+    }
+
+    impl OutputCounter {
+        fn add(&self, line: &str) -> OutputCount {
+            let Some(limit) = self.limit else {
+                return OutputCount::Within;
+            };
+            let size = line.len() + 1;
+            let before = self.total.fetch_add(size, sync::atomic::Ordering::Relaxed);
+            if before + size <= limit {
+                OutputCount::Within
+            } else if before <= limit {
+                OutputCount::Reached
+            } else {
+                OutputCount::Over
+            }
+        }
+    }
 }
 
 pub fn run_in_alternative_display<T: Sized>(cb: impl FnOnce() -> T) -> T {
@@ -266,6 +921,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn durations() {
+        use std::time::Duration;
+
+        assert_eq!(parse_duration("5s"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("2 m"), Some(Duration::from_mins(2)));
+        assert_eq!(parse_duration("100"), Some(Duration::from_millis(100)));
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration(&format!("{}m", u64::MAX)), None);
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("64KB"), Some(64 * 1024));
+        assert_eq!(parse_size("2 MB"), Some(2 * 1024 * 1024));
+        assert_eq!(parse_size("100"), Some(100));
+        assert_eq!(parse_size("lots"), None);
+    }
+
+    #[test]
+    fn ansi() {
+        assert_eq!(
+            strip_ansi_escapes("\x1b[1;31merror\x1b[0m: here"),
+            "error: here"
+        );
+        assert_eq!(
+            strip_ansi_escapes("\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x07"),
+            "link"
+        );
+        assert_eq!(strip_ansi_escapes("plain"), "plain");
+    }
+
     #[test]
     fn escaping() {
         let on = "testing 'escaping \\'' \"with \\\" quote\"";
@@ -17,11 +17,11 @@ fn pass() {
     let input = extract_tests(SPECIFICATION_UPPERCASE, false);
 
     let mut runner = Command::new("bun run examples/example_program.js {content} --uppercase");
-    let results = run_tests(&input.tests, &mut runner, &no_output_run_configuration());
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
     assert!(results.failures.is_empty());
 
     let mut runner = Command::new("bun run examples/example_program.js {content}");
-    let results = run_tests(&input.tests, &mut runner, &no_output_run_configuration());
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
     assert_eq!(results.failures.len(), 3);
 }
 
@@ -31,11 +31,11 @@ fn pass_stdout_stderr() {
 
     let mut runner =
         Command::new("bun run examples/example_stdin_stdout_program.js --uppercase --rpc");
-    let results = run_tests(&input.tests, &mut runner, &no_output_run_configuration());
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
     assert!(results.failures.is_empty());
 
     let mut runner = Command::new("bun run examples/example_stdin_stdout_program.js --rpc");
-    let results = run_tests(&input.tests, &mut runner, &no_output_run_configuration());
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
     assert_eq!(results.failures.len(), 3);
 }
 
@@ -45,7 +45,7 @@ fn pass_lists() {
 
     let mut runner =
         Command::new("bun run examples/example_program.js {content} --uppercase --use-lists");
-    let results = run_tests(&input.tests, &mut runner, &no_output_run_configuration());
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
     if !results.failures.is_empty() {
         for (test, _, out) in &results.failures {
             println!("{test}\n{out}");
@@ -54,7 +54,7 @@ fn pass_lists() {
     }
 
     let mut runner = Command::new("bun run examples/example_program.js --use-lists");
-    let results = run_tests(&input.tests, &mut runner, &no_output_run_configuration());
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
     assert_eq!(results.failures.len(), 3);
 }
 
@@ -68,7 +68,7 @@ fn program_crash() {
     ];
 
     for mut runner in commands.iter().copied().map(Command::new) {
-        let results = run_tests(&input.tests, &mut runner, &no_output_run_configuration());
+        let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
         assert_eq!(results.failures.len(), 1);
         assert_eq!(
             results.failures.get(0).map(|(lhs, _, _)| lhs.as_str()),
@@ -84,7 +84,7 @@ fn program_timeout() {
     let mut runner = Command::new(
         "bun run examples/example_stdin_stdout_program.js --uppercase --rpc --intentional-timeout --timeout 1000",
     );
-    let results = run_tests(&input.tests, &mut runner, &no_output_run_configuration());
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
     // test 2 does not run in under 1000 ms
     assert_eq!(
         &results.failures,
@@ -99,7 +99,7 @@ fn program_timeout() {
         "bun run examples/example_stdin_stdout_program.js --uppercase --rpc --intentional-timeout --timeout 5000",
     );
 
-    let results = run_tests(&input.tests, &mut runner, &no_output_run_configuration());
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
     assert!(results.failures.is_empty());
 }
 
@@ -113,7 +113,7 @@ fn program_options() {
     ];
 
     for mut runner in commands.iter().copied().map(Command::new) {
-        let results = run_tests(&input.tests, &mut runner, &no_output_run_configuration());
+        let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
         assert!(
             &results.failures.is_empty(),
             "found failures {failures:#?}",
@@ -121,3 +121,720 @@ fn program_options() {
         );
     }
 }
+
+#[test]
+fn test_timeouts() {
+    use std::time::Duration;
+
+    static SPECIFICATION_TIMEOUTS: &str = include_str!("../examples/specification.timeouts.md");
+
+    let input = extract_tests(SPECIFICATION_TIMEOUTS, false);
+
+    let timeouts: Vec<_> = input.tests().map(|test| test.timeout).collect();
+    assert_eq!(
+        timeouts,
+        [Some(Duration::from_secs(5)), Some(Duration::from_secs(2))]
+    );
+
+    // An invalid timeout is skipped (with a warning) rather than panicking
+    let content = "---\ntimeout: soon\n---\n\n### A\n\n```\na\n```\n";
+    let input = extract_tests(content, false);
+    assert_eq!(input.groups[0].cases[0].timeout, None);
+}
+
+#[test]
+fn transcripts() {
+    static SPECIFICATION_TRANSCRIPT: &str = include_str!("../examples/specification.transcript.md");
+
+    let input = extract_tests(SPECIFICATION_TRANSCRIPT, false);
+    assert_eq!(input.tests().count(), 5);
+    assert!(input.tests().all(|test| test.transcript));
+
+    // The runner is not used for transcripts
+    let mut runner = Command::new("false");
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+
+    let content = "### A\n\n```console\n$ echo a\nb\n$ true\n[2]\n```\n";
+    let input = extract_tests(content, false);
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    let (_, diffs, _) = &results.failures[0];
+    assert!(diffs.contains("$ echo a"));
+    assert!(diffs.contains("expected exit code 2, found 0"));
+}
+
+#[test]
+fn files() {
+    static SPECIFICATION_FILES: &str = include_str!("../examples/specification.files.md");
+
+    let input = extract_tests(SPECIFICATION_FILES, false);
+    assert_eq!(input.tests().count(), 1);
+    let test = &input.groups[0].cases[0];
+    assert_eq!(test.case, "src/a.txt");
+    assert_eq!(
+        test.files,
+        [
+            ("src/a.txt".to_owned(), "from a".to_owned()),
+            ("b.txt".to_owned(), "from b".to_owned())
+        ]
+    );
+
+    let mut runner = Command::new("cat --cwd {dir} {content}");
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+
+    let mut runner = Command::new("cat {dir}/b.txt");
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    assert_eq!(results.failures.len(), 1);
+}
+
+#[test]
+fn file_placeholder() {
+    let content = "### A\n\n```txt\nhello\n```\n\n```\nhello\n```\n";
+    let input = extract_tests(content, false);
+
+    // The case file is not reported as an extra file
+    let configuration = RunConfiguration {
+        report_extra_files: true,
+        ..no_output_run_configuration()
+    };
+    let mut runner = Command::new("cat {file}");
+    let results = run_tests(&input.groups, &mut runner, &configuration);
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+}
+
+#[test]
+fn expected_files() {
+    static SPECIFICATION_EXPECTED_FILES: &str =
+        include_str!("../examples/specification.expected-files.md");
+
+    let input = extract_tests(SPECIFICATION_EXPECTED_FILES, false);
+    assert_eq!(input.tests().count(), 1);
+    assert_eq!(input.groups[0].cases[0].expected, None);
+    assert_eq!(
+        input.groups[0].cases[0].expected_files,
+        [("out.txt".to_owned(), "hello".to_owned())]
+    );
+
+    let configuration = RunConfiguration {
+        report_extra_files: true,
+        ..no_output_run_configuration()
+    };
+
+    let mut runner = Command::new("cp --cwd {dir} {content} out.txt");
+    let results = run_tests(&input.groups, &mut runner, &configuration);
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+
+    let mut runner = Command::new("cp --cwd {dir} {content} other.txt");
+    let results = run_tests(&input.groups, &mut runner, &configuration);
+    let (_, diffs, _) = &results.failures[0];
+    assert!(diffs.contains("out.txt: could not read"), "{diffs}");
+    assert!(diffs.contains("other.txt: unexpected file"), "{diffs}");
+}
+
+#[test]
+fn sessions() {
+    /// Keeps a running total between calls, like a REPL
+    struct Total(i64);
+
+    impl spectra::Runner for Total {
+        fn run(&mut self, test: &spectra::Test) -> Result<(String, String), String> {
+            let value: i64 = test.case.trim().parse().map_err(|_| "expected number")?;
+            self.0 += value;
+            Ok((self.0.to_string(), String::new()))
+        }
+    }
+
+    static SPECIFICATION_SESSIONS: &str = include_str!("../examples/specification.sessions.md");
+
+    let input = extract_tests(SPECIFICATION_SESSIONS, false);
+    assert_eq!(input.tests().count(), 2);
+    assert!(input.groups[0].cases[0].session);
+    assert_eq!(
+        input.groups[0].cases[0].steps,
+        [
+            ("1".to_owned(), Some("1".to_owned())),
+            ("2".to_owned(), Some("3".to_owned()))
+        ]
+    );
+
+    let results = run_tests(&input.groups, &mut Total(0), &no_output_run_configuration());
+    assert_eq!(results.failures.len(), 1);
+    let (name, diffs, _) = &results.failures[0];
+    assert_eq!(name, "B");
+    assert!(diffs.starts_with("step 1 of 2:"), "{diffs}");
+
+    // Each step would be a new process
+    let mut runner = Command::new("echo {content}");
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    let (_, message, _) = &results.failures[0];
+    assert!(
+        message.contains("requires a runner which keeps state"),
+        "{message}"
+    );
+}
+
+#[test]
+fn groups() {
+    use spectra::utilities::filter::GlobPattern;
+
+    static SPECIFICATION_GROUPS: &str = include_str!("../examples/specification.groups.md");
+
+    let input = extract_tests(SPECIFICATION_GROUPS, false);
+    assert_eq!(input.groups.len(), 2);
+    assert_eq!(input.groups[0].cases.len(), 2);
+    assert_eq!(input.groups[0].case_name(1), "A [case 2/2]");
+    assert_eq!(input.groups[1].case_name(0), "B");
+
+    let mut runner = Command::new("echo {content}");
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    let names: Vec<_> = results
+        .failures
+        .iter()
+        .map(|(name, ..)| name.as_str())
+        .collect();
+    assert_eq!(names, ["A [case 1/2]", "A [case 2/2]", "B"]);
+
+    // Filters can target a group or a single case in it
+    for (pattern, failures) in [("A", 2), ("A [case 2/2]", 1), ("a [case 1/2]", 1)] {
+        let configuration = RunConfiguration {
+            filter: Some(Box::new(GlobPattern {
+                matcher: glob::Pattern::new(pattern).unwrap(),
+                positive: true,
+                case_sensitive: false,
+            })),
+            ..no_output_run_configuration()
+        };
+        let results = run_tests(&input.groups, &mut runner, &configuration);
+        assert_eq!(results.failures.len(), failures, "{pattern}");
+    }
+}
+
+#[test]
+fn tables() {
+    static SPECIFICATION_TABLES: &str = include_str!("../examples/specification.tables.md");
+    static SPECIFICATION_TABLES_ATTRIBUTES: &str =
+        include_str!("../examples/specification.tables-attributes.md");
+
+    let input = extract_tests(SPECIFICATION_TABLES, false);
+    assert_eq!(input.groups.len(), 1);
+    let group = &input.groups[0];
+    let cases: Vec<_> = group.cases.iter().map(|test| test.case.as_str()).collect();
+    assert_eq!(cases, ["a", "b | c", "d"]);
+    assert_eq!(group.case_name(1), "Uppercase [b | c]");
+
+    let mut runner = Command::new("sh -c \"echo $0 | tr a-z A-Z\" {content}");
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    let names: Vec<_> = results
+        .failures
+        .iter()
+        .map(|(name, ..)| name.as_str())
+        .collect();
+    assert_eq!(names, ["Uppercase [d]"]);
+
+    // Rows after a code block case keep the attributes of the heading and the section setup
+    let input = extract_tests(SPECIFICATION_TABLES_ATTRIBUTES, false);
+    let cases: Vec<_> = input.tests().map(|test| test.case.as_str()).collect();
+    assert_eq!(cases, ["S\na", "S\nb"]);
+    for test in input.tests() {
+        assert_eq!(test.tags, ["slow", "ignore"]);
+        assert_eq!(test.options, "--x");
+        assert!(test.is_ignored());
+    }
+}
+
+#[test]
+fn parameters() {
+    static SPECIFICATION_PARAMETERS: &str = include_str!("../examples/specification.parameters.md");
+
+    let input = extract_tests(SPECIFICATION_PARAMETERS, false);
+    assert_eq!(input.groups.len(), 2);
+
+    let group = &input.groups[0];
+    let cases: Vec<_> = group.cases.iter().map(|test| test.case.as_str()).collect();
+    assert_eq!(cases, ["strict 1", "loose 2"]);
+    assert_eq!(group.cases[1].expected.as_deref(), Some("loose: 2"));
+    assert_eq!(group.case_name(0), "Mode [mode=strict, level=1]");
+    assert_eq!(input.groups[1].case_name(1), "Table [name=b]");
+
+    let mut runner = Command::new("echo {content}");
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    let names: Vec<_> = results
+        .failures
+        .iter()
+        .map(|(name, ..)| name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        ["Mode [mode=strict, level=1]", "Mode [mode=loose, level=2]"]
+    );
+}
+
+#[test]
+fn includes() {
+    use spectra::{extract_tests_from_file, utilities::TemporaryDirectory};
+
+    let directory = TemporaryDirectory::new().unwrap();
+    let files = [
+        (
+            "main.md".to_owned(),
+            include_str!("../examples/includes/main.md").to_owned(),
+        ),
+        (
+            "shared/common.md".to_owned(),
+            include_str!("../examples/includes/shared/common.md").to_owned(),
+        ),
+        (
+            "shared/other.md".to_owned(),
+            include_str!("../examples/includes/shared/other.md").to_owned(),
+        ),
+    ];
+    directory.write_files(&files).unwrap();
+
+    let input = extract_tests_from_file(&directory.path().join("main.md"), false).unwrap();
+    let names: Vec<_> = input
+        .groups
+        .iter()
+        .map(|group| group.name.as_str())
+        .collect();
+    assert_eq!(names, ["A", "B", "C", "D"]);
+    let source = input.groups[1].source.as_ref().unwrap();
+    assert!(source.ends_with("shared/other.md"), "{source:?}");
+    assert_eq!(input.groups[3].source, None);
+
+    let cycle = [(
+        "shared/other.md".to_owned(),
+        "<!-- include: ../main.md -->\n".to_owned(),
+    )];
+    directory.write_files(&cycle).unwrap();
+    let result = extract_tests_from_file(&directory.path().join("main.md"), false);
+    assert!(result.is_err_and(|err| err.starts_with("include cycle")));
+
+    // Content is resolved relative to the current directory. The three tests fail with `echo x`
+    let content = "<!-- include: examples/specification.uppercase.md -->\n";
+    let runner = Command::new("echo x");
+    let result = spectra::run_tests_under_content(content, runner, &no_output_run_configuration());
+    assert_eq!(result, Err(3));
+
+    // A value which is not a path is skipped
+    let content = "---\ninclude: 5\n---\n\n### A\n\n```\na\n```\n";
+    let input = extract_tests(content, false);
+    assert!(input.includes.is_empty());
+    assert_eq!(input.tests().count(), 1);
+
+    let content = "<!-- include: not-a-file.md -->\n";
+    let runner = Command::new("echo x");
+    let result = spectra::run_tests_under_content(content, runner, &no_output_run_configuration());
+    assert_eq!(result, Err(1));
+}
+
+#[test]
+fn setup() {
+    static SPECIFICATION_SETUP: &str = include_str!("../examples/specification.setup.md");
+    static SPECIFICATION_SETUP_SESSION: &str =
+        include_str!("../examples/specification.setup-session.md");
+
+    let input = extract_tests(SPECIFICATION_SETUP, false);
+    let cases: Vec<_> = input.tests().map(|test| test.case.as_str()).collect();
+    assert_eq!(cases, ["S\na", "S\nb", "c"]);
+
+    // Counts how many times the setup was sent. The section without a setup resets it
+    let program = "echo start; n=0; buf=; while read -r line; do case $line in close) break;; setup) n=$((n+1)); setup=$buf; buf=; echo end;; end) echo \"$n:$setup|$buf\"; echo end; buf=;; *) buf=$buf$line;; esac; done";
+    let mut runner = Command::new(&format!("sh -c '{program}' --rpc"));
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+
+    // Sessions have the setup as part of their first step
+    let input = extract_tests(SPECIFICATION_SETUP_SESSION, false);
+    let steps = &input.groups[0].cases[0].steps;
+    assert_eq!(steps[0].0, "S\na");
+    assert_eq!(steps[1].0, "b");
+}
+
+#[test]
+fn tags() {
+    static SPECIFICATION_TAGS: &str = include_str!("../examples/specification.tags.md");
+
+    let input = extract_tests(SPECIFICATION_TAGS, false);
+    let names: Vec<_> = input
+        .groups
+        .iter()
+        .map(|group| group.name.as_str())
+        .collect();
+    assert_eq!(names, ["A", "B", "C (with generics)"]);
+    let tags: Vec<_> = input.tests().map(|test| test.tags.join(",")).collect();
+    assert_eq!(tags, ["slow,regression", "ignore", "slow"]);
+
+    let mut runner = Command::new("echo {content}");
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+    assert_eq!((results.count, results.ignored, results.skipped), (3, 1, 0));
+
+    let configuration = RunConfiguration {
+        tags: vec!["slow".to_owned()],
+        skip_tags: vec!["regression".to_owned()],
+        ..no_output_run_configuration()
+    };
+    let results = run_tests(&input.groups, &mut runner, &configuration);
+    assert_eq!((results.ignored, results.skipped), (0, 2));
+
+    // Only the platform attributes are platforms
+    let content = "### A {#only-fast}\n\n```\na\n```\n\n### B (only-linux)\n\n```\nb\n```\n";
+    let input = extract_tests(content, false);
+    let ignored: Vec<_> = input.tests().map(spectra::Test::is_ignored).collect();
+    assert_eq!(ignored, [false, !cfg!(target_os = "linux")]);
+}
+
+#[test]
+fn expected_failures() {
+    static SPECIFICATION_XFAIL: &str = include_str!("../examples/specification.xfail.md");
+
+    let input = extract_tests(SPECIFICATION_XFAIL, false);
+    assert!(input.tests().all(spectra::Test::is_expected_to_fail));
+
+    let mut runner = Command::new("echo {content}");
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    assert_eq!((results.xfailed, results.xpassed), (1, 1));
+    let (name, message, _) = &results.failures[0];
+    assert_eq!(name, "Fixed bug");
+    assert!(message.contains("unexpectedly passed"));
+}
+
+#[test]
+fn bench() {
+    use spectra::bench::{Baseline, BenchConfiguration, run_benchmarks};
+    use std::time::Duration;
+
+    static SPECIFICATION_BENCH: &str = include_str!("../examples/specification.bench.md");
+
+    let input = extract_tests(SPECIFICATION_BENCH, false);
+    let bench = BenchConfiguration {
+        warmup: 1,
+        iterations: 3,
+        ..BenchConfiguration::default()
+    };
+
+    let mut runner = Command::new("echo {content}");
+    let configuration = no_output_run_configuration();
+    let (results, measurements) = run_benchmarks(
+        &input.groups,
+        &mut runner,
+        &configuration,
+        &bench,
+        &Baseline::new(),
+    );
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+    assert_eq!((results.measured, results.ignored), (1, 1));
+    assert_eq!(measurements[0].name, "A");
+
+    // Everything is slower than nothing
+    let baseline = Baseline::from([("A".to_owned(), Duration::from_nanos(1))]);
+    let (results, _) = run_benchmarks(
+        &input.groups,
+        &mut runner,
+        &configuration,
+        &bench,
+        &baseline,
+    );
+    assert_eq!((results.measured, results.failures.len()), (0, 1));
+
+    // A failed save is reported but not counted as a test
+    let configuration = RunConfiguration {
+        quiet: true,
+        bench: Some(BenchConfiguration {
+            save: Some("not-a-directory/bench.json".into()),
+            ..bench
+        }),
+        ..RunConfiguration::default()
+    };
+    let result = spectra::run_tests_under_glob(
+        "examples/specification.uppercase.md",
+        Command::new("echo {content}"),
+        &configuration,
+    );
+    assert_eq!(result, Err(1));
+}
+
+#[test]
+fn resource_limits() {
+    static SPECIFICATION_LIMITS: &str = include_str!("../examples/specification.limits.md");
+
+    let line = "0123456789".repeat(4);
+    let input = extract_tests(SPECIFICATION_LIMITS, false);
+
+    // The output limit applies to each test rather than the whole process
+    let program = format!(
+        "echo start; while read -r line; do case $line in close) break;; end) echo {line}; echo end;; esac; done"
+    );
+    let mut runner = Command::new(&format!("sh -c '{program}' --rpc --limit-output 60"));
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+
+    let limits = [
+        (
+            "while :; do echo {line}; done",
+            "--limit-output 1KB",
+            "output",
+        ),
+        ("while :; do :; done", "--limit-cpu 1", "cpu"),
+        (
+            "true 3</dev/null 4</dev/null 5</dev/null 6</dev/null",
+            "--limit-files 4",
+            "open files",
+        ),
+    ];
+    for (program, limit, name) in limits {
+        let mut runner = Command::new(&format!("sh -c '{program}' {limit} --timeout 10000"));
+        let results = run_tests(
+            &input.groups[..1],
+            &mut runner,
+            &no_output_run_configuration(),
+        );
+        let (_, _, message) = &results.failures[0];
+        let expected = format!("RESOURCE LIMIT REACHED ({name})");
+        assert!(message.starts_with(&expected), "{message:?}");
+    }
+}
+
+#[test]
+fn rust_runner() {
+    use spectra::runners::compiled::rust::{Rust, RustConfiguration};
+    use spectra::{Runner, Test};
+
+    let input = extract_tests(SPECIFICATION_UPPERCASE, false);
+    let mut runner = Rust::new("examples/rust-runner", "get_result").unwrap();
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+
+    let test = Test {
+        case: "a".to_owned(),
+        ..Test::default()
+    };
+
+    // Output buffered by the library is flushed by its `spectra_flush`
+    let mut runner = Rust::new("examples/rust-runner", "shout").unwrap();
+    assert_eq!(
+        runner.run(&test),
+        Ok(("A".to_owned(), "shouting".to_owned()))
+    );
+
+    // Calls are made in a fork, so a panic fails the test rather than the run
+    let mut runner = Rust::new("examples/rust-runner", "crash").unwrap();
+    let message = runner.run(&test).unwrap_err();
+    assert!(message.starts_with("PANICKED: oh no"), "{message:?}");
+
+    // Statics only persist in-process
+    for (flags, second) in [("", "1"), (" --in-process", "2")] {
+        let argument = format!("examples/rust-runner::count{flags}");
+        let mut runner = Rust::new_from_argument(&argument, RustConfiguration::default()).unwrap();
+        let _ = runner.run(&test);
+        let (output, _) = runner.run(&test).unwrap();
+        assert_eq!(output, second, "{flags:?}");
+    }
+}
+
+#[test]
+fn rust_runner_abi() {
+    use spectra::runners::compiled::rust::Rust;
+    use spectra::{Runner, Test};
+
+    // The options are only passed with `SPECTRA_ABI_VERSION`
+    let mut runner = Rust::new("examples/rust-runner-abi", "get_result").unwrap();
+    for (options, expected) in [("", "ABC"), ("lowercase", "abc")] {
+        let test = Test {
+            case: "aBc".to_owned(),
+            options: options.to_owned(),
+            ..Test::default()
+        };
+        let (output, _) = runner.run(&test).unwrap();
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn rust_runner_isolate() {
+    use spectra::runners::compiled::rust::{Rust, RustConfiguration};
+    use spectra::{Runner, Test};
+
+    let configuration = RustConfiguration {
+        isolate: true,
+        executable: Some(env!("CARGO_BIN_EXE_spectra").into()),
+        ..RustConfiguration::default()
+    };
+
+    let input = extract_tests(SPECIFICATION_UPPERCASE, false);
+    let mut runner =
+        Rust::new_with_configuration("examples/rust-runner", "get_result", &configuration).unwrap();
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+    runner.close();
+
+    // The worker is restarted after a panic
+    let mut runner =
+        Rust::new_with_configuration("examples/rust-runner", "crash", &configuration).unwrap();
+    for _ in 0..2 {
+        let message = runner.run(&Test::default()).unwrap_err();
+        assert!(message.starts_with("PANICKED: oh no"), "{message:?}");
+    }
+    runner.close();
+
+    // A worker which does not start is an error
+    let result = Rust::new_with_configuration("examples/rust-runner", "missing", &configuration);
+    let message = result.err().unwrap().to_string();
+    assert!(
+        message.contains("does not have export missing"),
+        "{message}"
+    );
+
+    // The test binary does not have a `rust-worker` command
+    let configuration = RustConfiguration {
+        executable: None,
+        ..configuration
+    };
+    let result = Rust::new_with_configuration("examples/rust-runner", "get_result", &configuration);
+    assert!(result.is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn process_groups() {
+    use spectra::{Runner, Test};
+
+    let test = Test {
+        timeout: Some(std::time::Duration::from_millis(500)),
+        ..Test::default()
+    };
+
+    // The grandchild is left running when the program exits, and when it times out
+    let grandchild = "(sleep 1; touch {dir}/survived) >/dev/null 2>&1 &";
+    for program in [grandchild.to_owned(), format!("{grandchild} wait")] {
+        let mut runner = Command::new(&format!("sh -c '{program}'"));
+        let _ = runner.run(&test);
+        std::thread::sleep(std::time::Duration::from_millis(1500));
+        let path = runner.directory().unwrap().join("survived");
+        assert!(!path.exists(), "{program}");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn pseudo_terminal() {
+    use spectra::{Runner, Test};
+
+    // Written to a file as quoted arguments cannot contain backslashes
+    let directory = spectra::utilities::TemporaryDirectory::new().unwrap();
+    let script = [(
+        "terminal.sh".to_owned(),
+        r"[ -t 0 ] && [ -t 1 ] && echo tty; stty size; printf '\033[31mred\033[0m\n'".to_owned(),
+    )];
+    directory.write_files(&script).unwrap();
+    let script = directory.path().join("terminal.sh");
+    let script = script.display();
+    let test = Test::default();
+
+    let mut runner = Command::new(&format!("sh {script} --pty-size 100x30"));
+    let (output, _) = runner.run(&test).unwrap();
+    assert_eq!(output, "tty\n30 100\nred");
+
+    let mut runner = Command::new(&format!("sh {script} --pty --keep-ansi"));
+    let (output, _) = runner.run(&test).unwrap();
+    assert!(output.starts_with("tty\n"), "{output:?}");
+    assert!(output.ends_with("\u{1b}[31mred\u{1b}[0m"), "{output:?}");
+
+    // Without a pseudo-terminal
+    let mut runner = Command::new(&format!("sh {script}"));
+    let (output, _) = runner.run(&test).unwrap();
+    assert!(!output.starts_with("tty"), "{output:?}");
+}
+
+#[test]
+fn script_runner() {
+    use spectra::runners::{program::CommandConfiguration, script::Script};
+
+    let input = extract_tests(SPECIFICATION_UPPERCASE, false);
+
+    // Both runners are open at once, so their adapters must not clash
+    let mut python = Script::new_from_argument(
+        "examples/script-runner/uppercase.py::get_result",
+        CommandConfiguration::default(),
+    )
+    .unwrap();
+    let mut javascript = Script::new_from_argument(
+        "examples/script-runner/uppercase.mjs::getResult",
+        CommandConfiguration::default(),
+    )
+    .unwrap();
+
+    let results = run_tests(&input.groups, &mut python, &no_output_run_configuration());
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+    spectra::Runner::close(python);
+
+    let results = run_tests(
+        &input.groups,
+        &mut javascript,
+        &no_output_run_configuration(),
+    );
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+    spectra::Runner::close(javascript);
+}
+
+#[cfg(unix)]
+#[test]
+fn c_runner() {
+    use spectra::runners::compiled::c::{C, CConfiguration};
+    use spectra::{Runner, Test};
+
+    let directory = spectra::utilities::TemporaryDirectory::new().unwrap();
+    let library = directory.path().join("libuppercase.so");
+    let status = std::process::Command::new("cc")
+        .args(["-shared", "-fPIC", "-o"])
+        .arg(&library)
+        .arg("examples/c-runner/uppercase.c")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let library = library.display().to_string();
+
+    let input = extract_tests(SPECIFICATION_UPPERCASE, false);
+    let mut runner = C::new(&library, "get_result").unwrap();
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+
+    // Statics only persist in-process
+    let test = Test::default();
+    for (flags, second) in [("", "1"), (" --in-process", "2")] {
+        let argument = format!("{library}::count{flags}");
+        let mut runner = C::new_from_argument(&argument, CConfiguration::default()).unwrap();
+        let _ = runner.run(&test);
+        let (output, _) = runner.run(&test).unwrap();
+        assert_eq!(output, second, "{flags:?}");
+    }
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn wasm_runner() {
+    use spectra::runners::compiled::wasm::{Wasm, WasmConfiguration};
+
+    let directory = spectra::utilities::TemporaryDirectory::new().unwrap();
+    let path = directory.path().join("uppercase.wasm");
+    let module = wat::parse_file("examples/wasm-runner/uppercase.wat").unwrap();
+    std::fs::write(&path, module).unwrap();
+    let path = path.display().to_string();
+
+    let input = extract_tests(SPECIFICATION_UPPERCASE, false);
+    let mut runner = Wasm::new(&path, "get_result", &WasmConfiguration::default()).unwrap();
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    assert!(results.failures.is_empty(), "{:?}", results.failures);
+
+    // Fuel stops infinite loops
+    let mut runner = Wasm::new_from_argument(
+        &format!("{path}::spin --fuel 1000"),
+        WasmConfiguration::default(),
+    )
+    .unwrap();
+    let results = run_tests(&input.groups, &mut runner, &no_output_run_configuration());
+    let (_, _, message) = &results.failures[0];
+    assert!(message.starts_with("PROCESS TIMED OUT"), "{message:?}");
+}